@@ -0,0 +1,47 @@
+extern crate semaphore;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use semaphore::Semaphore;
+
+const ITERATIONS: usize = 1_000_000;
+
+/// Hand access back and forth between a held guard and a single pending acquisition,
+/// which is registered, woken and completed once per iteration.
+fn run(name: &str, parked: bool) {
+    let sema = Semaphore::new(2, ());
+    let mut cx = Context::from_waker(Waker::noop());
+    // Keep the semaphore from becoming inactive, which always takes the slow path.
+    let _pinned = sema.try_access().expect("guard acquisition failed");
+    let mut held = sema.try_access().expect("guard acquisition failed");
+    // A parked acquisition takes the waker slot, so the measured one falls back to the registry.
+    let mut parked_future = sema.acquire_async();
+    if parked {
+        assert!(Pin::new(&mut parked_future).poll(&mut cx).is_pending());
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut future = sema.acquire_async();
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        drop(held);
+        held = match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(result) => result.expect("guard acquisition failed"),
+            Poll::Pending => panic!("future still pending after release")
+        };
+    }
+    report(name, start.elapsed());
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let nanos = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+    println!("{:<24} {:>10.1} ns/op", name, nanos / ITERATIONS as f64);
+}
+
+fn main() {
+    run("single_waiter", false);
+    run("behind_parked_waiter", true);
+}
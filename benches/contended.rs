@@ -0,0 +1,54 @@
+extern crate semaphore;
+
+use std::env;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use semaphore::{Semaphore, ShardedSemaphore};
+
+const ITERATIONS: usize = 1_000_000;
+
+fn run<F: Fn() + Send + Sync + 'static>(name: &str, threads: usize, f: F) {
+    let f = Arc::new(f);
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles = (0..threads).map(|_| {
+        let f = f.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            for _ in 0..ITERATIONS {
+                f();
+            }
+        })
+    }).collect::<Vec<_>>();
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    report(name, threads, start.elapsed());
+}
+
+fn report(name: &str, threads: usize, elapsed: Duration) {
+    let ops = (threads * ITERATIONS) as f64;
+    let nanos = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+    println!("{:<24} {:>3} threads {:>10.1} ns/op", name, threads, nanos / ops);
+}
+
+fn main() {
+    // Allow oversubscribing the available CPUs to provoke contention on small machines.
+    let threads = env::var("BENCH_THREADS").ok().and_then(|threads| threads.parse().ok())
+        .unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+    let capacity = threads * 4;
+
+    let sema = Semaphore::new(capacity, ());
+    run("semaphore", threads, move || {
+        drop(sema.try_access());
+    });
+
+    let sharded = ShardedSemaphore::new(capacity, ());
+    run("sharded_semaphore", threads, move || {
+        drop(sharded.try_access());
+    });
+}
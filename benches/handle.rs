@@ -0,0 +1,37 @@
+extern crate semaphore;
+
+use std::time::{Duration, Instant};
+
+use semaphore::Semaphore;
+
+const ITERATIONS: usize = 10_000_000;
+
+fn run<F: Fn()>(name: &str, f: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    report(name, start.elapsed());
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let nanos = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+    println!("{:<24} {:>10.1} ns/op", name, nanos / ITERATIONS as f64);
+}
+
+fn main() {
+    let sema = Semaphore::new(1, ());
+
+    run("clone", || {
+        drop(sema.clone());
+    });
+
+    run("try_access", || {
+        drop(sema.try_access());
+    });
+
+    run("clone_and_try_access", || {
+        let sema = sema.clone();
+        drop(sema.try_access());
+    });
+}
@@ -0,0 +1,60 @@
+extern crate semaphore;
+
+use std::env;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use semaphore::Semaphore;
+use semaphore::strategy::Block;
+
+const ITERATIONS: usize = 1_000;
+
+fn run<F: Fn() + Send + Sync + 'static>(name: &str, threads: usize, f: F) {
+    let f = Arc::new(f);
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles = (0..threads).map(|_| {
+        let f = f.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            for _ in 0..ITERATIONS {
+                f();
+            }
+        })
+    }).collect::<Vec<_>>();
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    report(name, threads, start.elapsed());
+}
+
+fn report(name: &str, threads: usize, elapsed: Duration) {
+    let ops = (threads * ITERATIONS) as f64;
+    let nanos = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+    println!("{:<24} {:>3} threads {:>10.1} ns/op", name, threads, nanos / ops);
+}
+
+fn main() {
+    // Many more threads than capacity, with every thread yielding while it holds access,
+    // so that most acquisitions have to block.
+    let threads = env::var("BENCH_THREADS").ok().and_then(|threads| threads.parse().ok())
+        .unwrap_or(32);
+    let capacity = 2;
+
+    let sema = Semaphore::new(capacity, ());
+    run("queued_access", threads, move || {
+        let guard = sema.access();
+        thread::yield_now();
+        drop(guard);
+    });
+
+    let sema = Semaphore::new(capacity, ());
+    run("condvar_access", threads, move || {
+        let guard = sema.access_with(Block);
+        thread::yield_now();
+        drop(guard);
+    });
+}
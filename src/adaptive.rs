@@ -0,0 +1,139 @@
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use super::{AcquireError, Semaphore, SemaphoreGuard, TryAccessError};
+
+/// Semaphore whose capacity adapts to the outcomes reported by its guards.
+///
+/// The limit starts out at `min`, and is adjusted by an additive increase, multiplicative
+/// decrease (AIMD) policy: every success within the target latency raises it by one, while
+/// every failure, or success slower than the target latency, halves it. The limit always
+/// stays within `min` and `max`, inclusive. Lowering the limit does not revoke access that
+/// is already held, no further access is granted until enough of it has been released.
+pub struct AdaptiveSemaphore<T: ?Sized> {
+    semaphore: Semaphore<T>,
+    controller: Arc<Controller>
+}
+
+struct Controller {
+    min: usize,
+    max: usize,
+    target_latency: Duration,
+    limit: Mutex<usize>
+}
+
+impl Controller {
+    fn adjust<T: ?Sized, F: FnOnce(usize) -> usize>(&self, semaphore: &Semaphore<T>, f: F) {
+        let mut limit = self.limit.lock();
+        *limit = f(*limit).max(self.min).min(self.max);
+        semaphore.inner.raw.set_capacity(*limit);
+    }
+}
+
+impl<T: ?Sized> Clone for AdaptiveSemaphore<T> {
+    fn clone(&self) -> AdaptiveSemaphore<T> {
+        AdaptiveSemaphore {
+            semaphore: self.semaphore.clone(),
+            controller: self.controller.clone()
+        }
+    }
+}
+
+impl<T> AdaptiveSemaphore<T> {
+    /// Create a new adaptive semaphore around a resource, with a limit between `min` and `max`.
+    ///
+    /// Successes that take longer than `target_latency` count as a sign of congestion.
+    /// A `min` of zero is raised to one, since a limit of zero would never grant the access
+    /// through which it could be raised again. A `max` below `min` is raised to `min`.
+    pub fn new(min: usize, max: usize, target_latency: Duration, resource: T) -> Self {
+        let min = min.max(1);
+        let semaphore = Semaphore::new(0, resource);
+        // Acquisitions block while the limit is too low, since it can grow again.
+        semaphore.inner.raw.set_capacity(min);
+        AdaptiveSemaphore {
+            semaphore,
+            controller: Arc::new(Controller {
+                min,
+                max: max.max(min),
+                target_latency,
+                limit: Mutex::new(min)
+            })
+        }
+    }
+}
+
+impl<T: ?Sized> AdaptiveSemaphore<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource, like `Semaphore::try_access`.
+    pub fn try_access(&self) -> Result<AdaptiveGuard<T>, TryAccessError> {
+        self.semaphore.try_access().map(|guard| self.wrap(guard))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource, blocking like `Semaphore::access`.
+    pub fn access(&self) -> Result<AdaptiveGuard<T>, AcquireError> {
+        self.semaphore.access().map(|guard| self.wrap(guard))
+    }
+
+    fn wrap(&self, guard: SemaphoreGuard<T>) -> AdaptiveGuard<T> {
+        AdaptiveGuard {
+            guard,
+            semaphore: self.clone()
+        }
+    }
+
+    #[inline]
+    /// Current limit on the number of accesses granted at the same time.
+    pub fn limit(&self) -> usize {
+        *self.controller.limit.lock()
+    }
+
+    #[inline]
+    /// Borrow the underlying semaphore, for example to read its snapshot.
+    pub fn as_semaphore(&self) -> &Semaphore<T> {
+        &self.semaphore
+    }
+}
+
+/// RAII guard of an `AdaptiveSemaphore`, through which the outcome of the work is reported.
+///
+/// Returned from `AdaptiveSemaphore::try_access` and `AdaptiveSemaphore::access`.
+/// Reporting an outcome releases the access. Guards dropped without reporting
+/// one leave the limit unchanged.
+pub struct AdaptiveGuard<T: ?Sized> {
+    guard: SemaphoreGuard<T>,
+    semaphore: AdaptiveSemaphore<T>
+}
+
+impl<T: ?Sized> AdaptiveGuard<T> {
+    /// Report that the work succeeded after the given latency, and release the access.
+    ///
+    /// Raises the limit by one if the latency is within the target, and halves it otherwise.
+    pub fn record_success(self, latency: Duration) {
+        let controller = &self.semaphore.controller;
+        if latency <= controller.target_latency {
+            controller.adjust(&self.semaphore.semaphore, |limit| limit.saturating_add(1));
+        } else {
+            controller.adjust(&self.semaphore.semaphore, |limit| limit / 2);
+        }
+    }
+
+    /// Report that the work failed, and release the access.
+    ///
+    /// Halves the limit.
+    pub fn record_failure(self) {
+        self.semaphore.controller.adjust(&self.semaphore.semaphore, |limit| limit / 2);
+    }
+}
+
+impl<T: ?Sized> Deref for AdaptiveGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
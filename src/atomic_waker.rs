@@ -0,0 +1,97 @@
+use std::cell::UnsafeCell;
+use std::hint;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Waker;
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
+
+/// Slot for a single waker that can be registered and woken without taking a lock.
+///
+/// Follows the design of `AtomicWaker` from the `futures` crate: registration and waking
+/// each claim the slot through a state bit, and whichever comes second hands the waker on.
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>
+}
+
+// Safety: the waker is only accessed by whoever holds the `REGISTERING` or `WAKING` bit.
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    pub fn new() -> AtomicWaker {
+        AtomicWaker {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None)
+        }
+    }
+
+    /// Store the waker to be woken by the next call to `wake`.
+    ///
+    /// Must not be called concurrently with itself.
+    pub fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire) {
+            Ok(_) => unsafe {
+                let slot = &mut *self.waker.get();
+                let previous = match *slot {
+                    Some(ref current) if current.will_wake(waker) => None,
+                    _ => slot.replace(waker.clone())
+                };
+                if let Err(actual) = self.state.compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire) {
+                    // A wake arrived while registering, and left it to this thread to deliver.
+                    debug_assert_eq!(actual, REGISTERING | WAKING);
+                    let waker = slot.take();
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    drop(previous);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            },
+            Err(WAKING) => {
+                // A wake is in progress, so the task has to be polled again either way.
+                waker.wake_by_ref();
+                hint::spin_loop();
+            },
+            Err(_) => {}
+        }
+    }
+
+    /// Remove the registered waker, if any, without waking it.
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            },
+            // A concurrent registration wakes the waker itself once it sees the bit.
+            _ => None
+        }
+    }
+
+    #[inline]
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_registered(&self) -> bool {
+        if self.state.compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire).is_err() {
+            return false;
+        }
+        let registered = unsafe { (*self.waker.get()).is_some() };
+        if self.state.compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.swap(WAITING, Ordering::AcqRel);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+        registered
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+/// Rendezvous point where acquirers wait until a whole group has gathered.
+#[derive(Default)]
+pub struct Rendezvous {
+    state: Mutex<State>,
+    cond: Condvar
+}
+
+#[derive(Default)]
+struct State {
+    arrived: usize,
+    generation: u64
+}
+
+impl Rendezvous {
+    /// Block until `group_size` callers have arrived, including this one, or until the deadline passes.
+    ///
+    /// Returns `false` if the deadline passed first, in which case the caller has left again.
+    pub fn wait(&self, group_size: usize, deadline: Option<Instant>) -> bool {
+        let mut state = self.state.lock();
+        let generation = state.generation;
+        state.arrived += 1;
+        if state.arrived >= group_size {
+            state.arrived = 0;
+            state.generation = state.generation.wrapping_add(1);
+            self.cond.notify_all();
+            return true;
+        }
+        while state.generation == generation {
+            match deadline {
+                Some(deadline) => {
+                    if self.cond.wait_until(&mut state, deadline).timed_out() && state.generation == generation {
+                        state.arrived -= 1;
+                        return false;
+                    }
+                },
+                None => {
+                    self.cond.wait(&mut state);
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+use raw::RawSemaphore;
+use super::Semaphore;
+
+/// Global concurrency budget, shared among tenant semaphores by weight.
+///
+/// Every tenant created through `add_tenant` is a semaphore of its own, with a limit of
+/// `total * weight / sum_of_weights`, rounded down. The limits of all tenants are recomputed
+/// whenever a tenant is added, or its last handle is dropped. Lowering a limit does not
+/// revoke access that is already held, a tenant over its new limit is not granted any
+/// further access until enough has been released.
+pub struct BudgetSemaphore {
+    budget: Arc<Budget>
+}
+
+struct Budget {
+    total: usize,
+    tenants: Mutex<Tenants>
+}
+
+#[derive(Default)]
+struct Tenants {
+    next_id: usize,
+    entries: Vec<Tenant>
+}
+
+struct Tenant {
+    id: usize,
+    weight: usize,
+    raw: Weak<RawSemaphore>
+}
+
+impl Budget {
+    /// Recompute the limits of all tenants, with the list of tenants locked.
+    fn rebalance(&self, tenants: &Tenants) {
+        let weights = tenants.entries.iter().map(|tenant| tenant.weight).sum::<usize>();
+        for tenant in &tenants.entries {
+            if let Some(raw) = tenant.raw.upgrade() {
+                let limit = (self.total as u128 * tenant.weight as u128)
+                    .checked_div(weights as u128)
+                    .unwrap_or(0);
+                raw.set_capacity(limit as usize);
+            }
+        }
+    }
+}
+
+impl BudgetSemaphore {
+    /// Create a new budget of `total` accesses, without any tenants.
+    pub fn new(total: usize) -> Self {
+        BudgetSemaphore {
+            budget: Arc::new(Budget {
+                total,
+                tenants: Mutex::new(Tenants::default())
+            })
+        }
+    }
+
+    /// Add a tenant around a resource, which gets a share of the budget proportional to `weight`.
+    ///
+    /// The limits of the other tenants are lowered to make room for the new one.
+    /// Since its limit can change at any time, blocking acquisitions on a tenant wait
+    /// while its limit is too low, instead of failing with `AcquireError::NoCapacity`.
+    pub fn add_tenant<T>(&self, weight: usize, resource: T) -> TenantSemaphore<T> {
+        let semaphore = Semaphore::new(0, resource);
+        let mut tenants = self.budget.tenants.lock();
+        let id = tenants.next_id;
+        tenants.next_id += 1;
+        tenants.entries.push(Tenant {
+            id,
+            weight,
+            raw: Arc::downgrade(&semaphore.inner.raw)
+        });
+        self.budget.rebalance(&tenants);
+        TenantSemaphore {
+            semaphore,
+            membership: Arc::new(Membership {
+                budget: self.budget.clone(),
+                id
+            })
+        }
+    }
+
+    #[inline]
+    /// Total number of accesses shared among all tenants.
+    pub fn total(&self) -> usize {
+        self.budget.total
+    }
+}
+
+/// Membership of a tenant in a budget, which gives its share back once dropped.
+struct Membership {
+    budget: Arc<Budget>,
+    id: usize
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        let mut tenants = self.budget.tenants.lock();
+        tenants.entries.retain(|tenant| tenant.id != self.id);
+        self.budget.rebalance(&tenants);
+    }
+}
+
+/// Semaphore of a single tenant of a `BudgetSemaphore`.
+///
+/// Returned from `BudgetSemaphore::add_tenant`. Clones share the same tenant, which leaves
+/// the budget once all of them have been dropped.
+pub struct TenantSemaphore<T: ?Sized> {
+    semaphore: Semaphore<T>,
+    membership: Arc<Membership>
+}
+
+impl<T: ?Sized> Clone for TenantSemaphore<T> {
+    fn clone(&self) -> TenantSemaphore<T> {
+        TenantSemaphore {
+            semaphore: self.semaphore.clone(),
+            membership: self.membership.clone()
+        }
+    }
+}
+
+impl<T: ?Sized> TenantSemaphore<T> {
+    #[inline]
+    /// Current limit of this tenant, as its share of the budget.
+    pub fn limit(&self) -> usize {
+        self.semaphore.capacity()
+    }
+
+    #[inline]
+    /// Borrow the semaphore of this tenant, to use any of its acquisition methods.
+    pub fn as_semaphore(&self) -> &Semaphore<T> {
+        &self.semaphore
+    }
+}
@@ -0,0 +1,102 @@
+//! Cancellation tokens that can interrupt `Semaphore::access_cancellable`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::Waker;
+
+use parking_lot::Mutex;
+
+/// Token signalling that a blocked acquisition should give up.
+///
+/// Implement this trait to interrupt acquisitions from an existing cancellation mechanism.
+/// Blocked acquirers subscribe a waker while they wait, which the token has to wake
+/// once it is cancelled. A waker can be subscribed more than once during the same
+/// acquisition, and is unsubscribed again once the acquisition is over.
+pub trait Cancellable {
+    /// Whether the token has been cancelled.
+    fn is_cancelled(&self) -> bool;
+
+    /// Wake the given waker once the token is cancelled.
+    ///
+    /// If the token has already been cancelled, the waker may be woken right away or not at all.
+    fn subscribe(&self, waker: &Waker);
+
+    /// Stop waking a waker that was previously subscribed.
+    fn unsubscribe(&self, waker: &Waker);
+}
+
+impl<C: Cancellable + ?Sized> Cancellable for &C {
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        (**self).is_cancelled()
+    }
+
+    #[inline]
+    fn subscribe(&self, waker: &Waker) {
+        (**self).subscribe(waker)
+    }
+
+    #[inline]
+    fn unsubscribe(&self, waker: &Waker) {
+        (**self).unsubscribe(waker)
+    }
+}
+
+#[derive(Clone, Default)]
+/// Cancellation token backed by a shared `AtomicBool`.
+///
+/// Clones share the same state, so any clone can cancel acquisitions waiting on another.
+/// When created from an existing flag, setting that flag directly is still observed, but
+/// only `cancel` wakes acquirers that are already blocked.
+pub struct CancelFlag {
+    flag: Arc<AtomicBool>,
+    wakers: Arc<Mutex<Vec<Waker>>>
+}
+
+impl CancelFlag {
+    /// Create a new token that is not cancelled.
+    pub fn new() -> CancelFlag {
+        CancelFlag::default()
+    }
+
+    /// Cancel the token, waking all acquisitions waiting on it.
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        let wakers = self.wakers.lock().drain(..).collect::<Vec<_>>();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelFlag {
+    fn from(flag: Arc<AtomicBool>) -> CancelFlag {
+        CancelFlag {
+            flag,
+            wakers: Arc::default()
+        }
+    }
+}
+
+impl Cancellable for CancelFlag {
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    fn subscribe(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+        if !wakers.iter().any(|entry| entry.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        drop(wakers);
+        // A cancellation racing with the subscription may have drained the wakers already.
+        if self.is_cancelled() {
+            self.cancel();
+        }
+    }
+
+    fn unsubscribe(&self, waker: &Waker) {
+        self.wakers.lock().retain(|entry| !entry.will_wake(waker));
+    }
+}
@@ -0,0 +1,91 @@
+//! Shim mirroring the API of the former unstable `std::sync::Semaphore`.
+//!
+//! Eases porting code that was written against it, by keeping its method names and
+//! signatures, while building on this crate underneath:
+//!
+//! ```
+//! use semaphore::compat::Semaphore;
+//!
+//! let sem = Semaphore::new(5);
+//! sem.acquire();
+//! // ... access a shared resource ...
+//! sem.release();
+//! {
+//!     let _guard = sem.access();
+//!     // ... access a shared resource ...
+//! }
+//! ```
+//!
+//! ## Differences from the legacy API
+//!
+//! The legacy semaphore was a plain counter. `release` incremented it without bound, and
+//! `new` accepted a negative count. `Semaphore` keeps this behavior, but this crate limits
+//! concurrent access rather than counting: releasing without a matching acquisition raises
+//! the capacity of the underlying semaphore instead. A negative count is kept as a deficit,
+//! which has to be paid off by releases before any acquisition succeeds. Unlike the legacy
+//! guard, the one returned from `access` is a regular `SemaphoreGuard`, which owns its
+//! access and can be moved freely.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::SemaphoreGuard;
+
+/// Counting semaphore with the API of the former `std::sync::Semaphore`.
+pub struct Semaphore {
+    semaphore: super::Semaphore<()>,
+    /// Releases still owed before the count becomes positive.
+    deficit: AtomicUsize,
+    /// Accesses taken through `acquire`, which `release` gives back first.
+    acquired: AtomicUsize
+}
+
+impl Semaphore {
+    /// Create a new semaphore with the initial count specified.
+    ///
+    /// The count specified can be thought of as a number of resources, and a
+    /// call to `acquire` or `access` will block until at least one resource is
+    /// available. It is valid to initialize a semaphore with a negative count.
+    pub fn new(count: isize) -> Semaphore {
+        let semaphore = super::Semaphore::new(0, ());
+        // Acquisitions block while the count is zero, rather than failing.
+        semaphore.inner.raw.set_capacity(count.max(0) as usize);
+        Semaphore {
+            semaphore,
+            deficit: AtomicUsize::new(count.min(0).unsigned_abs()),
+            acquired: AtomicUsize::new(0)
+        }
+    }
+
+    /// Acquire a resource of this semaphore, blocking the current thread until it can do so.
+    ///
+    /// This method will block until the internal count of the semaphore is at least 1.
+    pub fn acquire(&self) {
+        self.semaphore.inner.raw.acquire_blocking(1, None, None);
+        self.acquired.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Release a resource from this semaphore.
+    ///
+    /// This will increment the number of resources in this semaphore by 1 and
+    /// will notify any pending waiters in `acquire` or `access` if necessary.
+    pub fn release(&self) {
+        let take = |count: usize| count.checked_sub(1);
+        if self.deficit.fetch_update(Ordering::SeqCst, Ordering::SeqCst, take).is_ok() {
+            return;
+        }
+        if self.acquired.fetch_update(Ordering::SeqCst, Ordering::SeqCst, take).is_ok() {
+            self.semaphore.inner.raw.release();
+        } else {
+            self.semaphore.add_permits(1);
+        }
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Acquire a resource of this semaphore, returning an RAII guard to release it when dropped.
+    ///
+    /// This function is semantically equivalent to an `acquire` followed by a `release`
+    /// when the guard returned is dropped.
+    pub fn access(&self) -> SemaphoreGuard<()> {
+        self.semaphore.access().expect("compat semaphore is never shut down")
+    }
+}
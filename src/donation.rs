@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use raw::RawSemaphore;
+
+/// Capacity that one semaphore has donated to another.
+///
+/// Returned from `Semaphore::donate`. The donation only moves capacity between the two
+/// semaphores, it neither acquires nor releases any access. It is reversed through
+/// `reclaim`, or when the donation is dropped.
+pub struct Donation {
+    from: Arc<RawSemaphore>,
+    to: Arc<RawSemaphore>,
+    amount: usize
+}
+
+pub fn new(from: &Arc<RawSemaphore>, to: &Arc<RawSemaphore>, n: usize) -> Donation {
+    let amount = from.take_capacity(n);
+    to.add_permits(amount);
+    Donation {
+        from: from.clone(),
+        to: to.clone(),
+        amount
+    }
+}
+
+impl Donation {
+    #[inline]
+    /// Amount of capacity that has been donated.
+    ///
+    /// This is less than requested if the donating semaphore did not have that much capacity.
+    pub fn amount(&self) -> usize {
+        self.amount
+    }
+
+    /// Move the donated capacity back to the semaphore that donated it.
+    pub fn reclaim(self) {}
+}
+
+impl Drop for Donation {
+    fn drop(&mut self) {
+        // Like any shrink, guards the recipient already holds stay valid beyond its capacity.
+        self.to.remove_permits(self.amount);
+        self.from.add_permits(self.amount);
+    }
+}
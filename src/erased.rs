@@ -0,0 +1,34 @@
+use std::any::Any;
+
+use super::SemaphoreGuard;
+
+/// RAII guard holding access to a semaphore, with the type of its resource erased.
+///
+/// Returned from `SemaphoreGuard::into_erased`. Guards of semaphores over different
+/// resource types can be kept together, for example in a `Vec<ErasedGuard>`. Dropping
+/// the erased guard releases its access just like dropping the original guard would.
+pub struct ErasedGuard {
+    guard: Box<dyn Any + Send>
+}
+
+pub fn new<T: ?Sized + Send + Sync + 'static>(guard: SemaphoreGuard<T>) -> ErasedGuard {
+    ErasedGuard { guard: Box::new(guard) }
+}
+
+impl ErasedGuard {
+    #[inline]
+    /// Whether this guard was erased from a guard over a resource of type `T`.
+    pub fn is<T: ?Sized + Send + Sync + 'static>(&self) -> bool {
+        self.guard.is::<SemaphoreGuard<T>>()
+    }
+
+    /// Recover the typed guard, if it was erased from a guard over a resource of type `T`.
+    ///
+    /// Otherwise the erased guard is handed back unchanged, still holding its access.
+    pub fn downcast<T: ?Sized + Send + Sync + 'static>(self) -> Result<SemaphoreGuard<T>, ErasedGuard> {
+        match self.guard.downcast::<SemaphoreGuard<T>>() {
+            Ok(guard) => Ok(*guard),
+            Err(guard) => Err(ErasedGuard { guard })
+        }
+    }
+}
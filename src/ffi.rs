@@ -0,0 +1,57 @@
+//! C-compatible functions for limiting access from non-Rust code.
+//!
+//! Semaphores and guards cross the boundary as opaque pointers. Every pointer returned
+//! from `semaphore_new` has to be passed to `semaphore_free` exactly once, and every
+//! non-null pointer returned from `semaphore_try_acquire` to `semaphore_release` exactly once.
+//! Guards should be released before the semaphore they were acquired from is freed.
+//! Releasing them afterwards is still memory-safe, since guards own their access,
+//! but is reported as a leak by the `leak-detection` feature.
+
+use std::ptr;
+
+use super::{Semaphore, SemaphoreGuard};
+
+/// Create a new semaphore with the given capacity, returning an owned handle to it.
+#[no_mangle]
+pub extern "C" fn semaphore_new(capacity: usize) -> *mut Semaphore<()> {
+    Box::into_raw(Box::new(Semaphore::new(capacity, ())))
+}
+
+/// Free a semaphore handle.
+///
+/// # Safety
+///
+/// `semaphore` has to be null, or a handle returned from `semaphore_new` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn semaphore_free(semaphore: *mut Semaphore<()>) {
+    if !semaphore.is_null() {
+        drop(Box::from_raw(semaphore));
+    }
+}
+
+/// Attempt to acquire access to the semaphore, returning an owned guard on success.
+///
+/// Returns null if the semaphore is out of capacity or shut down, or if it is null itself.
+///
+/// # Safety
+///
+/// `semaphore` has to be null, or a handle returned from `semaphore_new` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn semaphore_try_acquire(semaphore: *const Semaphore<()>) -> *mut SemaphoreGuard<()> {
+    match semaphore.as_ref().map(Semaphore::try_access) {
+        Some(Ok(guard)) => Box::into_raw(Box::new(guard)),
+        _ => ptr::null_mut()
+    }
+}
+
+/// Release the access held by a guard, and free it.
+///
+/// # Safety
+///
+/// `guard` has to be null, or a guard returned from `semaphore_try_acquire` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn semaphore_release(guard: *mut SemaphoreGuard<()>) {
+    if !guard.is_null() {
+        drop(Box::from_raw(guard));
+    }
+}
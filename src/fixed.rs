@@ -0,0 +1,52 @@
+use super::{Semaphore, TryAccessResult};
+
+/// Counting semaphore whose capacity is fixed at compile time.
+///
+/// Knowing the capacity statically allows acquisitions of a constant number of accesses
+/// to be checked at compile time, so that asking for more than the capacity is rejected
+/// by the compiler instead of always failing at runtime:
+///
+/// ```compile_fail
+/// # use semaphore::ConstSemaphore;
+/// let sema = ConstSemaphore::<_, 2>::new(());
+/// let guard = sema.try_access_many::<3>();
+/// ```
+pub struct ConstSemaphore<T: ?Sized, const CAP: usize> {
+    semaphore: Semaphore<T>
+}
+
+impl<T: ?Sized, const CAP: usize> Clone for ConstSemaphore<T, CAP> {
+    fn clone(&self) -> ConstSemaphore<T, CAP> {
+        ConstSemaphore {
+            semaphore: self.semaphore.clone()
+        }
+    }
+}
+
+impl<T, const CAP: usize> ConstSemaphore<T, CAP> {
+    /// Create a new semaphore around a resource, with a capacity of `CAP`.
+    pub fn new(resource: T) -> Self {
+        ConstSemaphore {
+            semaphore: Semaphore::new(CAP, resource)
+        }
+    }
+}
+
+impl<T: ?Sized, const CAP: usize> ConstSemaphore<T, CAP> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to acquire `N` accesses at once, returning a single guard holding all of them.
+    ///
+    /// Fails to compile if `N` exceeds the capacity of the semaphore.
+    /// Otherwise fails in the same way as `Semaphore::try_access_n`.
+    pub fn try_access_many<const N: usize>(&self) -> TryAccessResult<T> {
+        const { assert!(N <= CAP, "can not acquire more accesses than the capacity") };
+        self.semaphore.try_access_n(N)
+    }
+
+    #[inline]
+    /// Borrow the underlying semaphore, to use any of its acquisition methods.
+    pub fn as_semaphore(&self) -> &Semaphore<T> {
+        &self.semaphore
+    }
+}
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+use std::future::Future;
+#[cfg(feature = "debug-holders")]
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use guard;
+use super::{Semaphore, SemaphoreGuard, TryAccessError, TryAccessResult};
+
+/// Future that resolves once access to the semaphore has been acquired.
+///
+/// Returned from `Semaphore::acquire_async`.
+///
+/// ## Cancellation
+///
+/// Dropping the future before it has completed removes its waker from the semaphore.
+/// Access is only ever acquired from within `poll`, and is immediately handed out
+/// as a guard, so a dropped future never holds on to any capacity.
+pub struct AcquireFuture<T: ?Sized> {
+    semaphore: Semaphore<T>,
+    key: Option<usize>,
+    tag: Option<Cow<'static, str>>,
+    #[cfg(feature = "debug-holders")]
+    site: &'static Location<'static>
+}
+
+/// The future holds no self-references, so it does not need to be pinned to be polled.
+impl<T: ?Sized> Unpin for AcquireFuture<T> {}
+
+#[cfg_attr(feature = "debug-holders", track_caller)]
+pub fn new<T: ?Sized>(semaphore: &Semaphore<T>) -> AcquireFuture<T> {
+    AcquireFuture {
+        semaphore: semaphore.clone(),
+        key: None,
+        tag: None,
+        #[cfg(feature = "debug-holders")]
+        site: Location::caller()
+    }
+}
+
+impl<T: ?Sized> AcquireFuture<T> {
+    /// Attach the given tag to the guard this future resolves to.
+    ///
+    /// Like for `Semaphore::try_access_tagged`, the tag can be read back through
+    /// `SemaphoreGuard::tag`. With the `debug-holders` feature, it is also reported by
+    /// `Semaphore::diagnose_stuck`, which helps to find a guard that is held across
+    /// an `.await` that never resolves.
+    pub fn tagged<S: Into<Cow<'static, str>>>(mut self, tag: S) -> AcquireFuture<T> {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    fn complete(&mut self, mut result: TryAccessResult<T>) -> Poll<TryAccessResult<T>> {
+        if let Some(key) = self.key.take() {
+            self.semaphore.inner.raw.deregister_waker(key);
+        }
+        if let Ok(ref mut guard) = result {
+            if let Some(tag) = self.tag.take() {
+                guard::set_tag(guard, tag);
+            }
+        }
+        // Attribute the guard to the creation of the future, rather than to `poll`.
+        #[cfg(feature = "debug-holders")]
+        {
+            if let Ok(ref guard) = result {
+                guard::relocate(guard, self.site);
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<T: ?Sized> Future for AcquireFuture<T> {
+    type Output = TryAccessResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<TryAccessResult<T>> {
+        let this = self.get_mut();
+        match this.semaphore.try_access() {
+            Err(TryAccessError::NoCapacity) => {},
+            result => return this.complete(result)
+        }
+        this.key = Some(this.semaphore.inner.raw.register_waker(this.key, cx.waker()));
+        // Check again now that the waker is registered, so that a release
+        // racing with the registration is not missed.
+        match this.semaphore.try_access() {
+            Err(TryAccessError::NoCapacity) => Poll::Pending,
+            result => this.complete(result)
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for AcquireFuture<T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.semaphore.inner.raw.deregister_waker(key);
+        }
+    }
+}
+
+/// Stream of guards, yielding a new guard whenever capacity becomes available.
+///
+/// Returned from `Semaphore::permit_stream`.
+///
+/// The stream follows the shape of the `Stream` trait from the `futures` crate,
+/// so it can be adapted with `futures::stream::poll_fn`. It ends once the semaphore
+/// is shut down. Dropping the stream cancels any pending acquisition.
+pub struct PermitStream<T: ?Sized> {
+    semaphore: Semaphore<T>,
+    pending: Option<AcquireFuture<T>>,
+    done: bool
+}
+
+impl<T: ?Sized> Unpin for PermitStream<T> {}
+
+pub fn stream<T: ?Sized>(semaphore: &Semaphore<T>) -> PermitStream<T> {
+    PermitStream {
+        semaphore: semaphore.clone(),
+        pending: None,
+        done: false
+    }
+}
+
+impl<T: ?Sized> PermitStream<T> {
+    /// Attempt to pull out the next guard, registering the current task for wakeup
+    /// if no capacity is available yet.
+    ///
+    /// Returns `Poll::Ready(None)` once the semaphore has been shut down.
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<SemaphoreGuard<T>>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let result = {
+            let semaphore = &this.semaphore;
+            let pending = this.pending.get_or_insert_with(|| new(semaphore));
+            match Pin::new(pending).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending
+            }
+        };
+        this.pending = None;
+        match result {
+            Ok(guard) => Poll::Ready(Some(guard)),
+            Err(_) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
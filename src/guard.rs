@@ -1,40 +1,496 @@
-use std::ops::Deref;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
 use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Instant;
 
+use erased::{self, ErasedGuard};
+use lease::Lease;
+use paused::{self, PausedGuard};
 use raw::RawSemaphore;
+use super::{Semaphore, TryAccessError};
 
 /// RAII guard used to release access to the semaphore automatically when it falls out of scope.
 ///
 /// Returned from `Semaphore::try_access`. 
 ///
+/// ## Moving guards
+///
+/// Guards own their access and hold no borrow of the semaphore, so as long as the resource
+/// is `Send + Sync + 'static`, a guard is `Send + 'static` as well. It can be moved into a
+/// spawned thread, which then holds the access for the duration of its work:
+///
+/// ```
+/// # use semaphore::Semaphore;
+/// # use std::thread;
+/// let sema = Semaphore::new(2, ());
+/// let guard = sema.access().unwrap();
+/// thread::spawn(move || {
+///     // ... work while holding access ...
+///     drop(guard);
+/// }).join().unwrap();
+/// ```
+///
 /// ## Sharing guards
 ///
 /// There are cases where, once acquired, you want to share a guard between multiple threads
 /// of execution. This pattern can be implemented by wrapping the acquired guard into an [`Rc`][1]
-/// or [`Arc`][2] reference.
+/// or [`Arc`][2] reference. Within scoped tasks, such as those of `std::thread::scope` or
+/// `rayon::scope`, a plain reference works as well, since guards are `Sync` whenever the
+/// resource is `Send + Sync`.
 ///
 /// [1]: https://doc.rust-lang.org/std/rc/struct.Rc.html
 /// [2]: https://doc.rust-lang.org/std/sync/struct.Arc.html
-pub struct SemaphoreGuard<T> {
+pub struct SemaphoreGuard<T: ?Sized> {
     raw: Arc<RawSemaphore>,
-    resource: Arc<T>
+    resource: ManuallyDrop<Arc<T>>,
+    permits: usize,
+    sequence: u64,
+    tag: Option<Cow<'static, str>>,
+    lease: Option<Arc<Lease>>,
+    poison_on_panic: bool,
+    priority: u32,
+    saturated: bool,
+    /// Time of the acquisition, only taken if the semaphore has a hold threshold.
+    acquired_at: Option<Instant>,
+    /// Whether the capacity was grown for this guard, through `Semaphore::try_access_elastic`.
+    borrowed: bool,
+    /// Channel notified once the access has been released, through `Semaphore::try_access_notify`.
+    notify: Option<Sender<()>>,
+    #[cfg(feature = "debug-holders")]
+    holder: usize
+}
+
+#[cfg_attr(feature = "debug-holders", track_caller)]
+pub fn new<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>) -> SemaphoreGuard<T> {
+    with_permits(raw, resource, 1)
 }
 
-pub fn new<T>(raw: &Arc<RawSemaphore>, resource: &Arc<T>) -> SemaphoreGuard<T> {
+#[cfg_attr(feature = "debug-holders", track_caller)]
+pub fn with_permits<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>, permits: usize) -> SemaphoreGuard<T> {
     SemaphoreGuard {
         raw: raw.clone(),
-        resource: resource.clone()
+        resource: ManuallyDrop::new(resource.clone()),
+        permits,
+        sequence: raw.next_sequence(),
+        tag: None,
+        lease: None,
+        // Like a mutex, only a panic that starts while the guard is held poisons the semaphore.
+        poison_on_panic: raw.is_poisoning() && !thread::panicking(),
+        priority: 0,
+        saturated: false,
+        acquired_at: raw.hold_threshold().map(|_| Instant::now()),
+        borrowed: false,
+        notify: None,
+        #[cfg(feature = "debug-holders")]
+        holder: raw.holders().register(Location::caller())
+    }
+}
+
+#[cfg_attr(feature = "debug-holders", track_caller)]
+/// Create a guard for an acquisition that left `active` accesses held.
+pub fn acquired<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>, permits: usize, active: usize) -> SemaphoreGuard<T> {
+    let mut guard = with_permits(raw, resource, permits);
+    guard.saturated = active >= raw.capacity();
+    guard
+}
+
+#[cfg_attr(feature = "debug-holders", track_caller)]
+/// Acquire `permits` accesses for a guard over `resource` without blocking.
+pub fn try_reacquire<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>, permits: usize) -> Result<SemaphoreGuard<T>, TryAccessError> {
+    if raw.is_closed() {
+        return Err(TryAccessError::Shutdown);
+    }
+    if raw.is_poisoned() {
+        return Err(TryAccessError::Poisoned);
+    }
+    let active = match raw.try_acquire_n(permits) {
+        Some(active) => active,
+        None => return Err(TryAccessError::NoCapacity)
+    };
+    // A shutdown racing with this acquisition may not have seen it yet.
+    if raw.is_closed() {
+        raw.release_n(permits);
+        return Err(TryAccessError::Shutdown);
+    }
+    Ok(acquired(raw, resource, permits, active))
+}
+
+pub fn set_borrowed<T: ?Sized>(guard: &mut SemaphoreGuard<T>) {
+    guard.borrowed = true;
+}
+
+pub fn set_notify<T: ?Sized>(guard: &mut SemaphoreGuard<T>, tx: Sender<()>) {
+    guard.notify = Some(tx);
+}
+
+pub fn set_tag<T: ?Sized>(guard: &mut SemaphoreGuard<T>, tag: Cow<'static, str>) {
+    #[cfg(feature = "debug-holders")]
+    guard.raw.holders().retag(guard.holder, tag.clone());
+    guard.tag = Some(tag);
+}
+
+pub fn set_priority<T: ?Sized>(guard: &mut SemaphoreGuard<T>, priority: u32) {
+    guard.priority = priority;
+}
+
+pub fn set_lease<T: ?Sized>(guard: &mut SemaphoreGuard<T>, lease: Arc<Lease>) {
+    guard.lease = Some(lease);
+}
+
+#[cfg(feature = "debug-holders")]
+pub fn relocate<T: ?Sized>(guard: &SemaphoreGuard<T>, site: &'static Location<'static>) {
+    guard.raw.holders().relocate(guard.holder, site)
+}
+
+/// Guards only hold references to the resource, never the resource itself, so they
+/// can be moved freely even if the resource is `!Unpin`, for example out of a pinned future.
+impl<T: ?Sized> Unpin for SemaphoreGuard<T> {}
+
+impl<T: ?Sized> Drop for SemaphoreGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        // The resource reference has to be gone by the time the access is released,
+        // so that a shutdown handle observing zero active access can unwrap the resource.
+        unsafe { ManuallyDrop::drop(&mut self.resource) };
+        if self.poison_on_panic && thread::panicking() {
+            self.raw.poison();
+        }
+        #[cfg(feature = "debug-holders")]
+        let site = self.raw.holders().deregister(self.holder);
+        #[cfg(not(feature = "debug-holders"))]
+        let site = None;
+        if let Some(acquired_at) = self.acquired_at {
+            self.raw.check_hold(acquired_at.elapsed(), site);
+        }
+        if self.borrowed {
+            // Shrink before releasing, so that no waiter is woken for the borrowed capacity.
+            self.raw.remove_permits(1);
+        }
+        // The access of a reaped lease has already been released by the reaper.
+        if self.lease.as_ref().is_none_or(|lease| lease.release()) {
+            self.raw.release_n(self.permits)
+        }
+        if let Some(tx) = self.notify.take() {
+            // The receiver may be gone already, in which case nobody is waiting for the release.
+            let _ = tx.send(());
+        }
+        // Only report the leak once the access has been released, so that the semaphore
+        // does not stay active for good.
+        #[cfg(feature = "leak-detection")]
+        self.raw.check_orphaned();
+    }
+}
+
+/// Cloning a guard acquires another access to the semaphore, for as many accesses
+/// as the original holds. Each clone releases its own access when dropped.
+///
+/// # Panics
+///
+/// Panics if the semaphore is out of capacity, shut down or poisoned.
+/// Use `SemaphoreGuard::try_clone` to handle these cases.
+impl<T: ?Sized> Clone for SemaphoreGuard<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    fn clone(&self) -> SemaphoreGuard<T> {
+        match self.try_clone() {
+            Ok(guard) => guard,
+            Err(err) => panic!("failed to clone semaphore guard: {:?}", err)
+        }
+    }
+}
+
+impl<T: ?Sized> SemaphoreGuard<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to clone this guard by acquiring as many accesses as it holds once more.
+    ///
+    /// The clone carries the same tag and priority, but is never a lease, even if this guard is.
+    /// If the semaphore is out of capacity, shut down or poisoned, a `TryAccessError` will be returned.
+    pub fn try_clone(&self) -> Result<SemaphoreGuard<T>, TryAccessError> {
+        let mut guard = try_reacquire(&self.raw, &self.resource, self.permits)?;
+        guard.tag = self.tag.clone();
+        guard.priority = self.priority;
+        Ok(guard)
+    }
+
+    /// Release the access held by this guard for the time being, to be acquired again
+    /// through the returned `PausedGuard`.
+    ///
+    /// While paused, the access is available to other acquirers, for example while a
+    /// long-running task sleeps between batches of work. The paused guard does not
+    /// keep the resource alive, so it does not hold up a shutdown either.
+    pub fn pause(self) -> PausedGuard<T> {
+        paused::new(&self.raw, &self.resource, self.permits, self.tag.clone(), self.priority)
+    }
+
+    #[inline]
+    /// Number of accesses held by this guard, all of which are released together when it is dropped.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    #[inline]
+    /// Position of this guard in the order in which guards of the semaphore were handed out.
+    ///
+    /// Numbers start at zero and increase by one with every guard.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    #[inline]
+    /// Tag this guard was acquired with, if it was acquired through `Semaphore::try_access_tagged`.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(|tag| tag.as_ref())
+    }
+
+    #[inline]
+    /// Whether acquiring this guard took the semaphore to its capacity.
+    ///
+    /// Derived from the active count right after the acquisition, so exactly the acquirer that
+    /// took the last access learns that it saturated the semaphore, and can signal upstream to
+    /// slow down. Only recorded by `Semaphore::try_access` and its variants, as well as
+    /// `try_clone`. Guards acquired otherwise report `false`.
+    pub fn saturated_on_acquire(&self) -> bool {
+        self.saturated
+    }
+
+    #[inline]
+    /// Release the access held by this guard right away.
+    ///
+    /// Equivalent to dropping the guard, but states the intent more clearly
+    /// where the guard is released before the end of its scope.
+    pub fn release(self) {
+        drop(self)
+    }
+
+    #[track_caller]
+    /// Release `k` of the accesses held by this guard early, keeping the rest.
+    ///
+    /// The remaining accesses are released when the guard is dropped, as usual.
+    /// Returns the number of accesses that were released. Leases hold a single access,
+    /// which can only be released by dropping them, so for them this releases nothing
+    /// and returns `0`. In debug builds, releasing more than is held panics, pointing at
+    /// the caller. Otherwise, at most the accesses held by the guard are released.
+    pub fn release_some(&mut self, k: usize) -> usize {
+        debug_assert!(k <= self.permits,
+            "released more accesses than held by the guard, at {}", Location::caller());
+        let k = if self.lease.is_some() { 0 } else { k.min(self.permits) };
+        if k > 0 {
+            self.permits -= k;
+            self.raw.release_n(k);
+        }
+        k
+    }
+
+    /// Acquire `extra` accesses on top of those held by this guard, without blocking.
+    ///
+    /// Either all of them are acquired and added to this guard, to be released together
+    /// with the rest, or none at all, in which case the guard is left unchanged. This allows
+    /// acquiring a single access up front, and raising it once the cost of the work is known.
+    /// Fails in the same way as `Semaphore::try_access_n`. Leases hold a single access,
+    /// and always fail with `TryAccessError::NoCapacity`.
+    pub fn acquire_additional(&mut self, extra: usize) -> Result<(), TryAccessError> {
+        if self.raw.is_closed() {
+            return Err(TryAccessError::Shutdown);
+        }
+        if self.raw.is_poisoned() {
+            return Err(TryAccessError::Poisoned);
+        }
+        if self.lease.is_some() || self.raw.try_acquire_n(extra).is_none() {
+            return Err(TryAccessError::NoCapacity);
+        }
+        // A shutdown racing with this acquisition may not have seen it yet.
+        if self.raw.is_closed() {
+            self.raw.release_n(extra);
+            return Err(TryAccessError::Shutdown);
+        }
+        self.permits += extra;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Hand the accesses held by this guard over to another semaphore.
+    ///
+    /// The same number of accesses is acquired on `other` first, and only once that has
+    /// succeeded is this guard released, so there is no point at which neither is held.
+    /// If `other` is out of capacity or shut down, this guard is handed back along
+    /// with the error, still holding its access.
+    pub fn transfer_to(self, other: &Semaphore<T>) -> Result<SemaphoreGuard<T>, (SemaphoreGuard<T>, TryAccessError)> {
+        match other.try_access_n(self.permits) {
+            Ok(guard) => Ok(guard),
+            Err(err) => Err((self, err))
+        }
+    }
+
+    #[inline]
+    /// Priority this guard was acquired with through `Semaphore::access_with_priority`,
+    /// or `0` for guards acquired otherwise.
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    /// Whether an acquirer with a higher priority than this guard is waiting for access.
+    ///
+    /// Waiting acquirers are not granted access ahead of others because of their priority,
+    /// and holders are not preempted either. Instead, long-running holders of low priority
+    /// can check this signal periodically, and release their guard early to let the
+    /// waiting acquirer through.
+    pub fn has_higher_priority_waiter(&self) -> bool {
+        self.raw.has_waiter_above(self.priority)
+    }
+
+    /// Renew the lease of this guard, if it was acquired through `Semaphore::lease`.
+    ///
+    /// Returns whether the guard still holds its access, which is `false` once the lease
+    /// has been reaped by `Semaphore::reap_expired`. Guards that are not leases never
+    /// expire, so this always returns `true` for them.
+    pub fn renew(&self) -> bool {
+        self.lease.as_ref().is_none_or(|lease| lease.renew())
+    }
+
+    /// Whether the lease of this guard has run out without being renewed, or has been reaped.
+    ///
+    /// Guards that are not leases never expire.
+    pub fn is_expired(&self) -> bool {
+        self.lease.as_ref().is_some_and(|lease| lease.is_expired())
+    }
+
+    /// Project this guard to a mutable reference into the resource.
+    ///
+    /// Mutable access is only granted if this guard is guaranteed to be the only way
+    /// to reach the resource: the semaphore must have a capacity of one, and there
+    /// must be no references to the resource other than the ones held by this guard
+    /// and by the semaphore itself. Leases are never projected, since their access
+    /// can be reaped while they are held. Otherwise the guard is handed back unchanged.
+    /// While the projection is held, `Semaphore::resource` returns `None`.
+    pub fn map_mut<U: ?Sized, F>(self, f: F) -> Result<MappedMutGuard<T, U>, SemaphoreGuard<T>>
+        where F: FnOnce(&mut T) -> &mut U
+    {
+        let resource = match self.begin_exclusive() {
+            Some(resource) => resource,
+            None => return Err(self)
+        };
+        // Safety: `begin_exclusive` ensures that nothing else can access the resource
+        // for as long as this guard, which the mapped guard keeps alive, is held.
+        let value = f(unsafe { &mut *resource }) as *mut U;
+        Ok(MappedMutGuard {
+            value,
+            guard: self
+        })
+    }
+
+    /// Begin a projection of this guard to the resource, if it is the only way to reach it.
+    ///
+    /// The projection has to be ended through `RawSemaphore::end_projection`.
+    fn begin_exclusive(&self) -> Option<*mut T> {
+        // Any other reference to the resource can only be created by acquiring access,
+        // and with a capacity of one that access is held by this guard, or through
+        // `Semaphore::resource`, which backs off while the projection is held.
+        if self.permits != 1 || self.lease.is_some() {
+            return None;
+        }
+        // The capacity can not grow while the projection is held, but may have grown before.
+        self.raw.begin_projection();
+        if self.raw.capacity() != 1 || Arc::strong_count(&self.resource) > 2 {
+            self.raw.end_projection();
+            return None;
+        }
+        Some(Arc::as_ptr(&self.resource) as *mut T)
+    }
+
+    /// Run `f` with mutable access to the resource, under the same conditions as `map_mut`.
+    fn with_exclusive<R, F: FnOnce(&mut T) -> io::Result<R>>(&mut self, f: F) -> io::Result<R> {
+        let resource = match self.begin_exclusive() {
+            Some(resource) => resource,
+            None => return Err(io::Error::other("resource of the semaphore is not held exclusively"))
+        };
+        // The projection ends even if `f` panics.
+        struct Projection<'a>(&'a RawSemaphore);
+        impl<'a> Drop for Projection<'a> {
+            fn drop(&mut self) {
+                self.0.end_projection();
+            }
+        }
+        let _projection = Projection(&self.raw);
+        // Safety: `begin_exclusive` ensures that nothing else can access the resource
+        // until the projection ends, and the mutable borrow of this guard prevents
+        // it from being projected again in the meantime.
+        f(unsafe { &mut *resource })
+    }
+}
+
+/// Reading from a guard reads from the resource, so that the guard can be passed
+/// to functions such as `io::copy` directly.
+///
+/// Like `SemaphoreGuard::map_mut`, this needs the guard to be the only way to reach the
+/// resource: the semaphore must have a capacity of one, and the guard must hold its only
+/// access. Otherwise reading fails with an error of kind `io::ErrorKind::Other`.
+impl<T: ?Sized + Read> Read for SemaphoreGuard<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_exclusive(|resource| resource.read(buf))
     }
 }
 
-impl<T> Drop for SemaphoreGuard<T> {
+/// Writing to a guard writes to the resource, under the same conditions as reading from it.
+impl<T: ?Sized + Write> Write for SemaphoreGuard<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with_exclusive(|resource| resource.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.with_exclusive(|resource| resource.flush())
+    }
+}
+
+impl<T: ?Sized + Send + Sync + 'static> SemaphoreGuard<T> {
     #[inline]
+    /// Erase the type of the resource from this guard.
+    ///
+    /// The erased guard keeps holding the access, and releases it when dropped.
+    /// The typed guard can be recovered through `ErasedGuard::downcast`.
+    pub fn into_erased(self) -> ErasedGuard {
+        erased::new(self)
+    }
+}
+
+/// RAII guard holding exclusive access to a part of the resource of a semaphore.
+///
+/// Returned from `SemaphoreGuard::map_mut`. Releases the access when it falls out of scope.
+pub struct MappedMutGuard<T: ?Sized, U: ?Sized> {
+    value: *mut U,
+    guard: SemaphoreGuard<T>
+}
+
+impl<T: ?Sized, U: ?Sized> Drop for MappedMutGuard<T, U> {
     fn drop(&mut self) {
-        self.raw.release()
+        self.guard.raw.end_projection();
+    }
+}
+
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Send> Send for MappedMutGuard<T, U> {}
+unsafe impl<T: ?Sized + Send + Sync, U: ?Sized + Sync> Sync for MappedMutGuard<T, U> {}
+
+impl<T: ?Sized, U: ?Sized> Deref for MappedMutGuard<T, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &U {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> DerefMut for MappedMutGuard<T, U> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.value }
     }
 }
 
-impl<T: Sized> Deref for SemaphoreGuard<T> {
+impl<T: ?Sized> Deref for SemaphoreGuard<T> {
     type Target = T;
 
     #[inline]
@@ -42,3 +498,65 @@ impl<T: Sized> Deref for SemaphoreGuard<T> {
         self.resource.deref()
     }
 }
+
+impl<T: ?Sized> SemaphoreGuard<T> {
+    fn order_key(&self) -> (u64, *const RawSemaphore) {
+        (self.sequence, Arc::as_ptr(&self.raw))
+    }
+}
+
+/// Guards compare by identity, so every guard is only equal to itself.
+impl<T: ?Sized> PartialEq for SemaphoreGuard<T> {
+    fn eq(&self, other: &SemaphoreGuard<T>) -> bool {
+        self.order_key() == other.order_key()
+    }
+}
+
+impl<T: ?Sized> Eq for SemaphoreGuard<T> {}
+
+impl<T: ?Sized> PartialOrd for SemaphoreGuard<T> {
+    fn partial_cmp(&self, other: &SemaphoreGuard<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Guards are ordered by their `sequence`, that is, in the order in which they were acquired,
+/// regardless of the value of the resource. Older guards compare as less than newer ones, so a
+/// `BinaryHeap` of guards pops the newest first, and one of `Reverse` guards the oldest.
+///
+/// Sequences are only meaningful within a single semaphore. Guards of different semaphores
+/// with the same sequence are ordered consistently, but arbitrarily.
+impl<T: ?Sized> Ord for SemaphoreGuard<T> {
+    fn cmp(&self, other: &SemaphoreGuard<T>) -> Ordering {
+        self.order_key().cmp(&other.order_key())
+    }
+}
+
+/// RAII guard holding access to a semaphore, without a reference to its resource.
+///
+/// Returned from `Semaphore::try_acquire_permit`. Releases the access when it falls out of scope.
+pub struct PermitGuard {
+    raw: Arc<RawSemaphore>,
+    #[cfg(feature = "debug-holders")]
+    holder: usize
+}
+
+#[cfg_attr(feature = "debug-holders", track_caller)]
+pub fn permit(raw: &Arc<RawSemaphore>) -> PermitGuard {
+    PermitGuard {
+        raw: raw.clone(),
+        #[cfg(feature = "debug-holders")]
+        holder: raw.holders().register(Location::caller())
+    }
+}
+
+impl Drop for PermitGuard {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(feature = "debug-holders")]
+        self.raw.holders().deregister(self.holder);
+        self.raw.release();
+        #[cfg(feature = "leak-detection")]
+        self.raw.check_orphaned();
+    }
+}
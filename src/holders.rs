@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+#[derive(Clone, Debug)]
+/// Description of a guard that has been held for a long time.
+///
+/// Returned from `Semaphore::diagnose_stuck`.
+pub struct GuardInfo {
+    /// Location the guard was acquired at.
+    pub site: &'static Location<'static>,
+    /// Tag the guard was acquired with, if any.
+    pub tag: Option<Cow<'static, str>>,
+    /// Time the guard was acquired at.
+    pub acquired_at: Instant,
+    /// How long the guard had been held for when it was diagnosed.
+    pub held_for: Duration
+}
+
+/// Registry of the acquisition sites of all live guards of a semaphore.
+#[derive(Default)]
+pub struct Holders {
+    inner: Mutex<HoldersInner>
+}
+
+#[derive(Default)]
+struct HoldersInner {
+    next_id: usize,
+    holders: BTreeMap<usize, Holder>
+}
+
+struct Holder {
+    site: &'static Location<'static>,
+    tag: Option<Cow<'static, str>>,
+    acquired_at: Instant
+}
+
+impl Holders {
+    pub fn register(&self, site: &'static Location<'static>) -> usize {
+        let mut inner = self.inner.lock();
+        let id = inner.next_id;
+        inner.next_id = inner.next_id.wrapping_add(1);
+        inner.holders.insert(id, Holder {
+            site,
+            tag: None,
+            acquired_at: Instant::now()
+        });
+        id
+    }
+
+    pub fn relocate(&self, id: usize, site: &'static Location<'static>) {
+        if let Some(holder) = self.inner.lock().holders.get_mut(&id) {
+            holder.site = site;
+        }
+    }
+
+    pub fn retag(&self, id: usize, tag: Cow<'static, str>) {
+        if let Some(holder) = self.inner.lock().holders.get_mut(&id) {
+            holder.tag = Some(tag);
+        }
+    }
+
+    /// Remove a guard from the registry, returning its acquisition site.
+    pub fn deregister(&self, id: usize) -> Option<&'static Location<'static>> {
+        self.inner.lock().holders.remove(&id).map(|holder| holder.site)
+    }
+
+    /// Acquisition sites of all live guards, oldest first.
+    pub fn sites(&self) -> Vec<&'static Location<'static>> {
+        self.inner.lock().holders.values().map(|holder| holder.site).collect()
+    }
+
+    /// Live guards that have been held for longer than `older_than`, oldest first.
+    pub fn stuck(&self, older_than: Duration) -> Vec<GuardInfo> {
+        let now = Instant::now();
+        self.inner.lock().holders.values()
+            .map(|holder| GuardInfo {
+                site: holder.site,
+                tag: holder.tag.clone(),
+                acquired_at: holder.acquired_at,
+                held_for: now.saturating_duration_since(holder.acquired_at)
+            })
+            .filter(|info| info.held_for > older_than)
+            .collect()
+    }
+}
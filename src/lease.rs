@@ -0,0 +1,85 @@
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Lease state of a guard, which expires unless renewed within its time to live.
+pub struct Lease {
+    ttl: Duration,
+    renewed: Mutex<Instant>,
+    reaped: AtomicBool
+}
+
+impl Lease {
+    pub fn new(ttl: Duration) -> Lease {
+        Lease {
+            ttl,
+            renewed: Mutex::new(Instant::now()),
+            reaped: AtomicBool::new(false)
+        }
+    }
+
+    /// Extend the lease by its time to live, unless it has already been reaped.
+    pub fn renew(&self) -> bool {
+        let mut renewed = self.renewed.lock();
+        if self.reaped.load(Ordering::SeqCst) {
+            return false;
+        }
+        *renewed = Instant::now();
+        true
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.reaped.load(Ordering::SeqCst) || self.renewed.lock().elapsed() >= self.ttl
+    }
+
+    /// Take over the access held by the lease, if it has expired.
+    fn reap(&self, now: Instant) -> bool {
+        // Holding the lock excludes a concurrent renewal.
+        let renewed = self.renewed.lock();
+        if now.saturating_duration_since(*renewed) < self.ttl {
+            return false;
+        }
+        !self.reaped.swap(true, Ordering::SeqCst)
+    }
+
+    /// Take over the access held by the lease, when its guard is dropped.
+    ///
+    /// Returns `false` if the access has already been taken over by a reaper.
+    pub fn release(&self) -> bool {
+        !self.reaped.swap(true, Ordering::SeqCst)
+    }
+}
+
+/// Registry of the leases handed out by a semaphore.
+#[derive(Default)]
+pub struct Leases {
+    entries: Mutex<Vec<Weak<Lease>>>
+}
+
+impl Leases {
+    pub fn register(&self, lease: &Arc<Lease>) {
+        let mut entries = self.entries.lock();
+        entries.retain(|entry| entry.strong_count() > 0);
+        entries.push(Arc::downgrade(lease));
+    }
+
+    /// Reap all expired leases, returning how many accesses they were holding.
+    pub fn reap(&self) -> usize {
+        let now = Instant::now();
+        let mut reaped = 0;
+        self.entries.lock().retain(|entry| match entry.upgrade() {
+            Some(lease) => {
+                if lease.reap(now) {
+                    reaped += 1;
+                    false
+                } else {
+                    true
+                }
+            },
+            None => false
+        });
+        reaped
+    }
+}
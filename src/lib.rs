@@ -7,26 +7,146 @@
 //! - Provides RAII-style acquire/release API
 //! - Implements `Send`, `Sync` and `Clone`
 //!
-//! _* lock-free when not using the `shutdown` API_
+//! _* lock-free when not using the `shutdown` or blocking `access` APIs_
 
 extern crate parking_lot;
 
+use std::borrow::Cow;
+use std::num::NonZeroUsize;
+#[cfg(feature = "debug-holders")]
+use std::panic::Location;
 use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use parking_lot::RwLock;
 
+#[cfg(feature = "async")]
+mod atomic_waker;
+
+mod parker;
+
 mod raw;
-use raw::RawSemaphore;
+pub use raw::RawSemaphore;
+
+pub mod strategy;
+pub use strategy::AcquireStrategy;
+use strategy::Immediate;
+
+pub mod cancel;
+pub use cancel::Cancellable;
+
+mod observer;
+pub use observer::SemaphoreObserver;
+
+mod barrier;
+use barrier::Rendezvous;
+
+mod lease;
+use lease::{Lease, Leases};
 
 mod guard;
-pub use guard::SemaphoreGuard;
+pub use guard::{MappedMutGuard, PermitGuard, SemaphoreGuard};
 
 mod shutdown;
 pub use shutdown::ShutdownHandle;
 
+mod sharded;
+pub use sharded::ShardedSemaphore;
+
+mod mapped;
+pub use mapped::{MappedGuard, MappedSemaphore};
+
+mod rw;
+pub use rw::{RwReadGuard, RwSemaphore, RwWriteGuard};
+
+mod fixed;
+pub use fixed::ConstSemaphore;
+
+mod typed;
+pub use typed::TypedGuard;
+
+mod paused;
+pub use paused::PausedGuard;
+
+mod erased;
+pub use erased::ErasedGuard;
+
+mod owned;
+pub use owned::OwnedSemaphore;
+
+mod local;
+pub use local::{LocalGuard, LocalSemaphore};
+
+mod budget;
+pub use budget::{BudgetSemaphore, TenantSemaphore};
+
+mod adaptive;
+pub use adaptive::{AdaptiveGuard, AdaptiveSemaphore};
+
+mod tiered;
+pub use tiered::{ReplenishPolicy, Tier, TieredGuard, TieredSemaphore};
+
+mod single_flight;
+pub use single_flight::SingleFlightSemaphore;
+
+pub mod compat;
+
+mod reservation;
+pub use reservation::Reservation;
+
+mod donation;
+pub use donation::Donation;
+
+mod ticket;
+pub use ticket::WaitTicket;
+
+#[cfg(feature = "debug-holders")]
+mod holders;
+#[cfg(feature = "debug-holders")]
+pub use holders::GuardInfo;
+
+#[cfg(feature = "fairness-trace")]
+mod trace;
+#[cfg(feature = "fairness-trace")]
+pub use trace::FAIRNESS_TRACE_LEN;
+#[cfg(feature = "throughput")]
+mod throughput;
+#[cfg(feature = "throughput")]
+pub use throughput::THROUGHPUT_WINDOW_SECS;
+
+mod snapshot;
+pub use snapshot::SemaphoreSnapshot;
+
+mod weak;
+pub use weak::WeakSemaphore;
+
+mod registry;
+pub use registry::SemaphoreRegistry;
+
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::WaitStats;
+
+#[cfg(feature = "async")]
+mod future;
+#[cfg(feature = "async")]
+pub use future::{AcquireFuture, PermitStream};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 #[cfg(test)]
 mod tests;
 
+/// Number of CPUs available to the process, or 1 if that can not be determined.
+fn available_cpus() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
 /// Result returned from `Semaphore::try_access`.
 pub type TryAccessResult<T> = Result<SemaphoreGuard<T>, TryAccessError>;
 
@@ -40,36 +160,370 @@ pub enum TryAccessError {
     Shutdown,
     /// This semaphore has no more capacity to grant further access.
     /// Other access needs to be released before this semaphore can grant more.
-    NoCapacity
+    NoCapacity,
+    /// A thread panicked while holding access to the resource of this semaphore,
+    /// which may have left it in an inconsistent state.
+    ///
+    /// Only returned from semaphores created through `Semaphore::with_poisoning`,
+    /// until `Semaphore::clear_poison` is called.
+    Poisoned
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// Error indicating a failure to take the resource out of an idle semaphore.
+///
+/// Returned from `Semaphore::try_take_if_idle`.
+pub enum TryTakeError {
+    /// Access to the resource is currently held.
+    InUse,
+    /// Other handles to the semaphore or references to the resource exist.
+    Shared,
+    /// This semaphore has shut down, and no longer holds the resource.
+    Shutdown
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Load on a semaphore with a soft limit, as observed by an acquisition.
+///
+/// Returned from `Semaphore::try_access_graded`.
+pub enum LimitState {
+    /// The acquisition kept the number of active accesses within the soft limit.
+    Normal,
+    /// The acquisition took the number of active accesses over the soft limit.
+    /// It still succeeded, but optional work should be shed.
+    OverSoft
+}
+
+/// Result returned from the blocking `Semaphore::access` family.
+pub type AcquireResult<T> = Result<SemaphoreGuard<T>, AcquireError>;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// Error indicating a failure to acquire access to the resource
+/// behind the semaphore while waiting for capacity.
+///
+/// Returned from the blocking methods such as `Semaphore::access` and `Semaphore::access_timeout`.
+pub enum AcquireError {
+    /// This semaphore has shut down and will no longer grant access to the underlying resource.
+    Shutdown,
+    /// The timeout expired before capacity became available.
+    Timeout,
+    /// The acquisition was cancelled before capacity became available.
+    Cancelled,
+    /// This semaphore does not have enough capacity to ever grant the requested access,
+    /// for example because its capacity is zero.
+    NoCapacity,
+    /// A thread panicked while holding access to the resource of this semaphore.
+    /// See `TryAccessError::Poisoned`.
+    Poisoned
+}
+
+impl AcquireError {
+    #[inline]
+    /// Whether the acquisition failed because the semaphore has shut down.
+    pub fn is_shutdown(&self) -> bool {
+        *self == AcquireError::Shutdown
+    }
+
+    #[inline]
+    /// Whether the acquisition failed because the timeout expired.
+    pub fn is_timeout(&self) -> bool {
+        *self == AcquireError::Timeout
+    }
+
+    #[inline]
+    /// Whether the acquisition failed because it was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self == AcquireError::Cancelled
+    }
+
+    #[inline]
+    /// Whether the acquisition failed because the semaphore can never grant it.
+    pub fn is_no_capacity(&self) -> bool {
+        *self == AcquireError::NoCapacity
+    }
+
+    #[inline]
+    /// Whether the acquisition failed because the semaphore is poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        *self == AcquireError::Poisoned
+    }
+}
+
+/// Waiting acquisitions that gave up without the semaphore shutting down
+/// turn into `TryAccessError::NoCapacity`.
+impl From<AcquireError> for TryAccessError {
+    fn from(err: AcquireError) -> TryAccessError {
+        match err {
+            AcquireError::Shutdown => TryAccessError::Shutdown,
+            AcquireError::Poisoned => TryAccessError::Poisoned,
+            AcquireError::Timeout | AcquireError::Cancelled | AcquireError::NoCapacity => {
+                TryAccessError::NoCapacity
+            }
+        }
+    }
 }
 
 /// Counting semaphore to control concurrent access to a common resource.
-pub struct Semaphore<T> {
+///
+/// ## Leaking handles
+///
+/// Forgetting a clone of a semaphore through `mem::forget` leaks the shared state,
+/// but does not affect how access is counted: guards hold their own reference to it,
+/// and keep acquiring and releasing correctly, and shutting down through any remaining
+/// clone still completes once all guards are gone. Only operations that require
+/// being the last handle, such as `into_inner` or `try_into_owned`, will fail from then on.
+pub struct Semaphore<T: ?Sized> {
+    inner: Arc<Inner<T>>
+}
+
+/// State shared by all clones of a semaphore, behind a single reference count.
+///
+/// The raw semaphore stays in an allocation of its own. Guards, permit guards, donations
+/// and budget tenants hold on to it without knowing the type of the resource, and it
+/// has to outlive the last clone while guards are still held. Clones dereference one
+/// pointer to reach the shared state and a second one to reach the raw semaphore.
+struct Inner<T: ?Sized> {
     raw: Arc<RawSemaphore>,
-    resource: Arc<RwLock<Option<Arc<T>>>>
+    resource: RwLock<Option<Arc<T>>>,
+    leases: Leases,
+    soft_limit: usize,
+    rendezvous: Rendezvous
 }
 
-impl<T> Clone for Semaphore<T> {
+#[cfg(feature = "leak-detection")]
+impl<T: ?Sized> Drop for Inner<T> {
+    fn drop(&mut self) {
+        self.raw.orphan();
+    }
+}
+
+impl<T: ?Sized> Clone for Semaphore<T> {
     fn clone(&self) -> Semaphore<T> {
         Semaphore {
-            raw: self.raw.clone(),
-            resource: self.resource.clone()
+            inner: self.inner.clone()
         }
     }
 }
 
+/// Semaphores compare by identity, not by the value of their resource.
+///
+/// Two semaphores are equal if and only if one is a clone of the other,
+/// meaning that they share capacity and the underlying resource.
+impl<T: ?Sized> PartialEq for Semaphore<T> {
+    fn eq(&self, other: &Semaphore<T>) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<T: ?Sized> Eq for Semaphore<T> {}
+
+/// Create a semaphore with a capacity of 1 around a resource,
+/// so that it grants exclusive access like a mutex.
+impl<T> From<T> for Semaphore<T> {
+    fn from(resource: T) -> Semaphore<T> {
+        Semaphore::new(1, resource)
+    }
+}
+
 impl<T> Semaphore<T> {
     /// Create a new semaphore around a resource.
     ///
     /// The semaphore will limit the number of processes that can access
     /// the underlying resource at every point in time to the specified capacity.
     pub fn new(capacity: usize, resource: T) -> Self {
+        Semaphore::from_arc(capacity, Arc::new(resource))
+    }
+
+    /// Create a new semaphore around a resource, with a capacity that is known not to be zero.
+    ///
+    /// Unlike `new`, this rules out a semaphore that can never grant access at compile time,
+    /// which makes it the preferred constructor wherever the capacity is not dynamic.
+    pub fn with_nonzero_capacity(capacity: NonZeroUsize, resource: T) -> Self {
+        Semaphore::new(capacity.get(), resource)
+    }
+
+    /// Create a new semaphore around a resource, with a soft limit below its capacity.
+    ///
+    /// The semaphore grants up to `hard_limit` accesses at the same time, like one created
+    /// through `new`. Acquisitions through `try_access_graded` additionally report whether
+    /// they took the number of active accesses over `soft_limit`, so that callers can shed
+    /// optional work before the hard limit is reached. A soft limit above the hard limit
+    /// is lowered to the hard limit.
+    pub fn with_soft_limit(soft_limit: usize, hard_limit: usize, resource: T) -> Self {
+        Semaphore::from_parts(RawSemaphore::new(hard_limit), soft_limit.min(hard_limit), Arc::new(resource))
+    }
+
+    /// Create a new semaphore around a resource, which is poisoned by panics while holding access.
+    ///
+    /// Like `std::sync::Mutex`, if a thread panics while holding a guard, all further attempts
+    /// to access the resource fail with a `Poisoned` error until `clear_poison` is called.
+    /// Guards acquired through `try_acquire_permit` do not reference the resource, and
+    /// neither poison the semaphore nor are refused because of it.
+    pub fn with_poisoning(capacity: usize, resource: T) -> Self {
+        let semaphore = Semaphore::new(capacity, resource);
+        semaphore.inner.raw.enable_poisoning();
+        semaphore
+    }
+
+    /// Create a new semaphore around a resource, which reports its lifecycle events to `observer`.
+    ///
+    /// The observer is told about every acquisition and release, every failed attempt
+    /// through `try_access` and its variants, and the semaphore shutting down.
+    /// Semaphores created without an observer do not pay for any of this.
+    pub fn with_observer<O: SemaphoreObserver + 'static>(capacity: usize, resource: T, observer: O) -> Self {
+        let raw = RawSemaphore::with_observer(capacity, Some(Box::new(observer)));
+        Semaphore::from_parts(raw, usize::MAX, Arc::new(resource))
+    }
+
+    /// Create a new semaphore around a resource, which reports guards held for longer than `threshold`.
+    ///
+    /// If the `hold-warnings` feature is enabled, whenever a guard is released after being held
+    /// for longer than the threshold, a warning with the time it was held for is printed to
+    /// standard error, along with its acquisition site if the `debug-holders` feature is enabled.
+    /// Without the feature, nothing is reported. Unlike `set_deadlock_threshold`, this
+    /// reports holders that were slow, but did eventually release their access.
+    /// Semaphores created without a threshold do not take the time of acquisitions at all.
+    pub fn with_hold_threshold(capacity: usize, resource: T, threshold: Duration) -> Self {
+        let mut raw = RawSemaphore::new(capacity);
+        raw.set_hold_threshold(threshold);
+        Semaphore::from_parts(raw, usize::MAX, Arc::new(resource))
+    }
+
+    /// Create a new semaphore around a resource, with one unit of capacity per available CPU.
+    ///
+    /// Falls back to a capacity of 1 if the available parallelism can not be determined.
+    pub fn for_cpus(resource: T) -> Self {
+        Semaphore::for_cpus_scaled(resource, 1)
+    }
+
+    /// Create a new semaphore around a resource, with `factor` units of capacity per available CPU.
+    ///
+    /// Falls back to a single CPU if the available parallelism can not be determined.
+    /// The capacity is always at least 1, even for a factor of zero.
+    pub fn for_cpus_scaled(resource: T, factor: usize) -> Self {
+        Semaphore::new(available_cpus().saturating_mul(factor).max(1), resource)
+    }
+
+    /// Extract the underlying resource, if this is the last handle to the semaphore.
+    ///
+    /// Succeeds only if there are no clones of this semaphore or weak handles to it,
+    /// no outstanding guards or shutdown handles, and no other references to the resource.
+    /// Otherwise the semaphore is handed back unchanged. A semaphore that has been shut down
+    /// no longer holds its resource, and is handed back as well.
+    pub fn into_inner(self) -> Result<T, Semaphore<T>> {
+        // Clones hold a reference to the shared state, and guards and handles to the raw
+        // semaphore. Weak handles could be upgraded to new clones at any time, so they
+        // have to be gone as well. Without other references, no new ones can be created.
+        let shared = Arc::strong_count(&self.inner) != 1 || Arc::weak_count(&self.inner) != 0;
+        if shared || Arc::strong_count(&self.inner.raw) != 1 {
+            return Err(self);
+        }
+        let resource = self.inner.resource.write().take();
+        match resource.map(Arc::try_unwrap) {
+            Some(Ok(resource)) => Ok(resource),
+            Some(Err(resource)) => {
+                *self.inner.resource.write() = Some(resource);
+                Err(self)
+            },
+            None => Err(self)
+        }
+    }
+
+    /// Take the underlying resource out of this semaphore, if it is idle and this is its last handle.
+    ///
+    /// Like `into_inner`, this requires that there are no clones of this semaphore, no
+    /// outstanding guards or shutdown handles, and no other references to the resource,
+    /// but only borrows the semaphore, so that it can be used for evicting idle entries
+    /// of a cache without blocking. On success, the semaphore is shut down. Otherwise
+    /// it is left unchanged, and `TryTakeError::InUse` is returned while access is held,
+    /// `TryTakeError::Shared` while other handles or references exist, and
+    /// `TryTakeError::Shutdown` once the resource is gone.
+    pub fn try_take_if_idle(&self) -> Result<T, TryTakeError> {
+        // Holding the write lock keeps acquirers from taking a reference to the resource.
+        let mut resource = self.inner.resource.write();
+        if resource.is_none() {
+            return Err(TryTakeError::Shutdown);
+        }
+        if self.inner.raw.is_active() {
+            return Err(TryTakeError::InUse);
+        }
+        if Arc::strong_count(&self.inner) != 1 || Arc::strong_count(&self.inner.raw) != 1 {
+            return Err(TryTakeError::Shared);
+        }
+        match resource.take().map(Arc::try_unwrap) {
+            Some(Ok(taken)) => {
+                self.inner.raw.close();
+                Ok(taken)
+            },
+            Some(Err(shared)) => {
+                *resource = Some(shared);
+                Err(TryTakeError::Shared)
+            },
+            None => Err(TryTakeError::Shutdown)
+        }
+    }
+
+    /// Shut down the semaphore, and block until all access has been released.
+    ///
+    /// Equivalent to calling `wait` on the handle returned from `shutdown`, so the resource
+    /// is only returned to the first caller to shut the semaphore down. Any access
+    /// still held by the calling thread will make this block forever.
+    pub fn drain_and_shutdown(&self) -> Option<T> {
+        self.shutdown().wait()
+    }
+}
+
+impl<T: ?Sized> Semaphore<T> {
+    /// Create a new semaphore around a resource that is already shared via an `Arc`.
+    ///
+    /// This is also the way to create a semaphore around an unsized resource,
+    /// such as a slice or a trait object.
+    ///
+    /// Guards will hand out the same `Arc` that was passed in here, so no further
+    /// allocation takes place. Note that as long as references to the resource
+    /// exist outside of the semaphore, `ShutdownHandle::wait` is unable to extract it
+    /// and returns `None`. Use `ShutdownHandle::wait_arc` to get the shared reference instead.
+    pub fn from_arc(capacity: usize, resource: Arc<T>) -> Self {
+        Semaphore::from_parts(RawSemaphore::new(capacity), usize::MAX, resource)
+    }
+
+    fn from_parts(raw: RawSemaphore, soft_limit: usize, resource: Arc<T>) -> Self {
         Semaphore {
-            raw: Arc::new(RawSemaphore::new(capacity)),
-            resource: Arc::new(RwLock::new(Some(Arc::new(resource))))
+            inner: Arc::new(Inner {
+                raw: Arc::new(raw),
+                resource: RwLock::new(Some(resource)),
+                leases: Leases::default(),
+                soft_limit,
+                rendezvous: Rendezvous::default()
+            })
         }
     }
 
+    /// Acquire access through `acquire` without blocking, returning the resource along with
+    /// the number of accesses active right after the acquisition.
+    ///
+    /// The acquisition happens while the resource is read, so that a concurrent shutdown
+    /// either waits for the acquired access, or makes the acquisition fail. Every failure
+    /// is reported to the observer.
+    fn try_acquire_resource<F>(&self, acquire: F) -> Result<(Arc<T>, usize), TryAccessError>
+        where F: FnOnce(&RawSemaphore) -> Option<usize>
+    {
+        if self.inner.raw.is_closed() {
+            return Err(self.inner.raw.deny(TryAccessError::Shutdown));
+        }
+        if self.inner.raw.is_poisoned() {
+            return Err(self.inner.raw.deny(TryAccessError::Poisoned));
+        }
+        match *self.inner.resource.read() {
+            Some(ref resource) => match acquire(&self.inner.raw) {
+                Some(active) => Ok((resource.clone(), active)),
+                None => Err(self.inner.raw.deny(TryAccessError::NoCapacity))
+            },
+            None => Err(self.inner.raw.deny(TryAccessError::Shutdown))
+        }
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
     #[inline]
     /// Attempt to access the underlying resource of this semaphore.
     ///
@@ -77,26 +531,811 @@ impl<T> Semaphore<T> {
     /// guard structure which will release the access when it falls out of scope.
     /// If the semaphore is out of capacity or shut down, a `TryAccessError` will be returned.
     pub fn try_access(&self) -> TryAccessResult<T> {
-        if let Some(ref resource) = *self.resource.read() {
-            if self.raw.try_acquire() {
-                Ok(guard::new(&self.raw, resource))
+        self.access_with(Immediate)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// wrapping the guard into a type of the caller's choosing right away.
+    ///
+    /// `f` is only called if access was acquired, for example to put the guard into an `Rc`
+    /// or a pooled allocation. Fails in the same way as `try_access`.
+    pub fn try_access_with_guard<G, F: FnOnce(SemaphoreGuard<T>) -> G>(&self, f: F) -> Result<G, TryAccessError> {
+        self.try_access().map(f)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// additionally returning a reference to the resource that outlives the guard.
+    ///
+    /// The returned `Arc` points to the same allocation that the guard dereferences to.
+    /// If the semaphore is out of capacity or shut down, a `TryAccessError` will be returned.
+    /// While the `Arc` is alive, `ShutdownHandle::wait` can not extract the resource and
+    /// returns `None`, use `ShutdownHandle::wait_arc` instead.
+    pub fn try_access_arc(&self) -> Result<(SemaphoreGuard<T>, Arc<T>), TryAccessError> {
+        let (resource, active) = self.try_acquire_resource(|raw| raw.try_acquire_n(1))?;
+        Ok((guard::acquired(&self.inner.raw, &resource, 1, active), resource))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// additionally reporting how much capacity was left right after the acquisition.
+    ///
+    /// The headroom is derived from the acquisition itself, so unlike a separate query it
+    /// can not be skewed by concurrent acquisitions and releases. Callers can use it to
+    /// ramp up while headroom is plentiful and back off as it approaches zero.
+    /// If the semaphore is out of capacity or shut down, a `TryAccessError` will be returned.
+    pub fn try_access_with_headroom(&self) -> Result<(SemaphoreGuard<T>, usize), TryAccessError> {
+        let (resource, active) = self.try_acquire_resource(|raw| raw.try_acquire_n(1))?;
+        let headroom = self.inner.raw.capacity().saturating_sub(active);
+        Ok((guard::acquired(&self.inner.raw, &resource, 1, active), headroom))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// additionally reporting whether the acquisition went over the soft limit.
+    ///
+    /// Succeeds up to the capacity of the semaphore, which acts as the hard limit.
+    /// Semaphores not created through `with_soft_limit` have no soft limit, and always
+    /// report `LimitState::Normal`. Fails in the same way as `try_access`.
+    pub fn try_access_graded(&self) -> Result<(SemaphoreGuard<T>, LimitState), TryAccessError> {
+        let (resource, active) = self.try_acquire_resource(|raw| raw.try_acquire_n(1))?;
+        let state = if active > self.inner.soft_limit {
+            LimitState::OverSoft
+        } else {
+            LimitState::Normal
+        };
+        Ok((guard::acquired(&self.inner.raw, &resource, 1, active), state))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// growing its capacity by one if it is out of capacity, but below `max`.
+    ///
+    /// Capacity grown this way is borrowed by the returned guard, and given back when the
+    /// guard is dropped, so that the semaphore scales up under a burst of acquisitions and
+    /// returns to its base capacity afterwards. Fails in the same way as `try_access`
+    /// once the capacity has reached `max`, or if a concurrent acquisition takes the
+    /// borrowed capacity first.
+    pub fn try_access_elastic(&self, max: usize) -> TryAccessResult<T> {
+        let mut borrowed = false;
+        let (resource, active) = self.try_acquire_resource(|raw| {
+            raw.try_acquire_n(1).or_else(|| {
+                borrowed = true;
+                raw.try_acquire_borrowed(max)
+            })
+        })?;
+        let mut guard = guard::acquired(&self.inner.raw, &resource, 1, active);
+        if borrowed {
+            guard::set_borrowed(&mut guard);
+        }
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// attaching the given tag to the returned guard.
+    ///
+    /// The tag can be read back through `SemaphoreGuard::tag`, for example to find out
+    /// which request is holding on to access. Fails in the same way as `try_access`.
+    pub fn try_access_tagged<S: Into<Cow<'static, str>>>(&self, tag: S) -> TryAccessResult<T> {
+        let mut guard = self.try_access()?;
+        guard::set_tag(&mut guard, tag.into());
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore, sending `()` on `tx`
+    /// once the returned guard has released its access.
+    ///
+    /// The message is sent after the access has been released, so a coordinator receiving it
+    /// can acquire the access right away. Send errors are ignored, for example if the receiver
+    /// has been dropped. Clones of the guard do not notify. Fails in the same way as `try_access`.
+    pub fn try_access_notify(&self, tx: Sender<()>) -> TryAccessResult<T> {
+        let mut guard = self.try_access()?;
+        guard::set_notify(&mut guard, tx);
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to acquire access to this semaphore without accessing the underlying resource.
+    ///
+    /// The returned guard counts against the capacity like any other guard, but does not hold
+    /// a reference to the resource, which makes acquiring it cheaper than `try_access`.
+    /// If the semaphore is out of capacity or shut down, a `TryAccessError` will be returned.
+    pub fn try_acquire_permit(&self) -> Result<PermitGuard, TryAccessError> {
+        if self.inner.raw.is_closed() {
+            return Err(self.inner.raw.deny(TryAccessError::Shutdown));
+        }
+        if !self.inner.raw.try_acquire() {
+            return Err(self.inner.raw.deny(TryAccessError::NoCapacity));
+        }
+        // A shutdown racing with this acquisition may not have seen it yet.
+        if self.inner.raw.is_closed() {
+            self.inner.raw.release();
+            return Err(self.inner.raw.deny(TryAccessError::Shutdown));
+        }
+        Ok(guard::permit(&self.inner.raw))
+    }
+
+    /// Attempt to reserve access to the underlying resource of this semaphore,
+    /// to be turned into a guard later.
+    ///
+    /// The access is acquired right away, so that work which must not start without it
+    /// can be prepared before calling `Reservation::commit`, which then can not fail.
+    /// If the semaphore is out of capacity or shut down, a `TryAccessError` will be returned.
+    pub fn reserve(&self) -> Result<Reservation<T>, TryAccessError> {
+        let (resource, _) = self.try_acquire_resource(|raw| raw.try_acquire_n(1))?;
+        Ok(reservation::new(&self.inner.raw, &resource))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, blocking the current thread
+    /// until capacity becomes available.
+    ///
+    /// If the semaphore has a capacity of zero, `AcquireError::NoCapacity` is returned right away,
+    /// or as soon as `remove_permits` shrinks the capacity to zero while blocked.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access(&self) -> AcquireResult<T> {
+        self.access_n_until(1, None)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, blocking the current thread
+    /// until capacity becomes available or the timeout expires.
+    ///
+    /// If the timeout expires, `AcquireError::Timeout` will be returned.
+    /// If the semaphore has a capacity of zero, `AcquireError::NoCapacity` is returned right away,
+    /// or as soon as `remove_permits` shrinks the capacity to zero while blocked.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access_timeout(&self, timeout: Duration) -> AcquireResult<T> {
+        self.access_n_until(1, Some(Instant::now() + timeout))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to acquire `n` accesses at once, returning a single guard holding all of them.
+    ///
+    /// Either all `n` accesses are acquired, or none at all.
+    /// If fewer than `n` accesses are available, `TryAccessError::NoCapacity` will be returned.
+    /// If the semaphore is shut down, `TryAccessError::Shutdown` will be returned.
+    pub fn try_access_n(&self, n: usize) -> TryAccessResult<T> {
+        let (resource, active) = self.try_acquire_resource(|raw| raw.try_acquire_n(n))?;
+        Ok(guard::acquired(&self.inner.raw, &resource, n, active))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to acquire `N` accesses at once, returning a guard with the number in its type.
+    ///
+    /// Like `try_access_n`, but the returned `TypedGuard` makes the number of accesses
+    /// it holds visible to the type system. Fails in the same way as `try_access_n`.
+    pub fn try_access_typed<const N: usize>(&self) -> Result<TypedGuard<T, N>, TryAccessError> {
+        self.try_access_n(N).map(typed::new)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, only blocking if it is busy.
+    ///
+    /// While the `utilization` is below `threshold`, this attempts to acquire access without
+    /// blocking. Once utilization reaches the threshold, or if the attempt loses a race for
+    /// the remaining capacity, this blocks like `access_timeout` for at most `max_wait`.
+    /// A threshold of `0.0` always blocks like `access_timeout`, a threshold above `1.0`
+    /// blocks only when the attempt to acquire without blocking has failed.
+    pub fn access_adaptive_block(&self, threshold: f64, max_wait: Duration) -> AcquireResult<T> {
+        if self.utilization() < threshold {
+            match self.try_access() {
+                Ok(guard) => return Ok(guard),
+                Err(TryAccessError::Shutdown) => return Err(AcquireError::Shutdown),
+                Err(TryAccessError::Poisoned) => return Err(AcquireError::Poisoned),
+                Err(TryAccessError::NoCapacity) => {}
+            }
+        }
+        self.access_timeout(max_wait)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Acquire `n` accesses at once, blocking the current thread until they are
+    /// available simultaneously or the timeout expires.
+    ///
+    /// The accesses are only ever acquired together, so a timeout never leaves
+    /// any of them behind. If the timeout expires, `AcquireError::Timeout` will be returned.
+    /// If `n` exceeds the capacity of the semaphore, `AcquireError::NoCapacity` is returned
+    /// right away, or as soon as the capacity is shrunk below `n` while blocked, since the
+    /// accesses could never be acquired.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access_n_timeout(&self, n: usize, timeout: Duration) -> AcquireResult<T> {
+        self.access_n_until(n, Some(Instant::now() + timeout))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, blocking the current thread
+    /// until capacity becomes available or the given token is cancelled.
+    ///
+    /// A blocked acquirer is woken as soon as the token is cancelled, through the waker
+    /// it subscribes to the token. If the token is cancelled, `AcquireError::Cancelled`
+    /// will be returned, even if capacity is available.
+    /// If the semaphore has a capacity of zero, `AcquireError::NoCapacity` is returned right away,
+    /// or as soon as `remove_permits` shrinks the capacity to zero while blocked.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access_cancellable<C: Cancellable + ?Sized>(&self, token: &C) -> AcquireResult<T> {
+        self.access_n_cancellable(1, None, Some(&token))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore like `access`, with the given priority.
+    ///
+    /// While blocked, the acquirer is visible to current holders of lower priority through
+    /// `SemaphoreGuard::has_higher_priority_waiter`, so that they can cooperatively release
+    /// their access early. The priority does not change the order in which blocked acquirers
+    /// are granted access. The returned guard carries the priority as well.
+    pub fn access_with_priority(&self, priority: u32) -> AcquireResult<T> {
+        self.inner.raw.enter_priority(priority);
+        let result = self.access();
+        self.inner.raw.leave_priority(priority);
+        let mut guard = result?;
+        guard::set_priority(&mut guard, priority);
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, and then block until `group_size`
+    /// acquirers, including this one, have gathered, so that they all proceed together.
+    ///
+    /// Every member of a group holds its access while waiting for the others, so all callers
+    /// are expected to pass the same group size. If `group_size` exceeds the capacity of the
+    /// semaphore, `AcquireError::NoCapacity` is returned right away, since the group could
+    /// never gather. Otherwise, this fails in the same way as `access`, and blocks forever
+    /// if the group never fills up. Use `barrier_access_timeout` to bound the wait.
+    pub fn barrier_access(&self, group_size: usize) -> AcquireResult<T> {
+        self.barrier_access_until(group_size, None)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore like `barrier_access`, giving up
+    /// once the timeout expires before the group has gathered.
+    ///
+    /// An acquirer that times out releases its access again and leaves the group, so that
+    /// it is no longer counted towards it. If the timeout expires, `AcquireError::Timeout`
+    /// will be returned.
+    pub fn barrier_access_timeout(&self, group_size: usize, timeout: Duration) -> AcquireResult<T> {
+        self.barrier_access_until(group_size, Some(Instant::now() + timeout))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    fn barrier_access_until(&self, group_size: usize, deadline: Option<Instant>) -> AcquireResult<T> {
+        if group_size > self.inner.raw.capacity() {
+            return Err(AcquireError::NoCapacity);
+        }
+        let guard = self.access_n_until(1, deadline)?;
+        if !self.inner.rendezvous.wait(group_size, deadline) {
+            return Err(AcquireError::Timeout);
+        }
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    fn access_n_until(&self, n: usize, deadline: Option<Instant>) -> AcquireResult<T> {
+        self.access_n_cancellable(n, deadline, None)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    fn access_n_cancellable(&self, n: usize, deadline: Option<Instant>, token: Option<&dyn Cancellable>) -> AcquireResult<T> {
+        if self.inner.raw.is_closed() {
+            return Err(AcquireError::Shutdown);
+        }
+        if self.inner.raw.is_poisoned() {
+            return Err(AcquireError::Poisoned);
+        }
+        if self.inner.raw.exceeds_capacity(n) {
+            return Err(AcquireError::NoCapacity);
+        }
+        if !self.inner.raw.acquire_blocking(n, deadline, token) {
+            if self.inner.raw.is_closed() {
+                return Err(AcquireError::Shutdown);
+            }
+            if token.is_some_and(|token| token.is_cancelled()) {
+                return Err(AcquireError::Cancelled);
+            }
+            if self.inner.raw.exceeds_capacity(n) {
+                return Err(AcquireError::NoCapacity);
+            }
+            return Err(AcquireError::Timeout);
+        }
+        if !self.inner.raw.is_closed() {
+            if let Some(ref resource) = *self.inner.resource.read() {
+                return Ok(guard::with_permits(&self.inner.raw, resource, n));
+            }
+        }
+        self.inner.raw.release_n(n);
+        Err(AcquireError::Shutdown)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore under a lease, blocking like `access`.
+    ///
+    /// The lease expires unless it is renewed through `SemaphoreGuard::renew` at least
+    /// once every `ttl`. Expired leases are not reclaimed on their own: `reap_expired`
+    /// has to be called, for example by a watchdog, to release their access.
+    pub fn lease(&self, ttl: Duration) -> AcquireResult<T> {
+        let mut guard = self.access()?;
+        let lease = Arc::new(Lease::new(ttl));
+        self.inner.leases.register(&lease);
+        guard::set_lease(&mut guard, lease);
+        Ok(guard)
+    }
+
+    /// Release the access held by all expired leases, returning how many were reaped.
+    ///
+    /// The guard of a reaped lease stays valid, but no longer counts against the capacity
+    /// of the semaphore, and fails to `renew`. Since it still references the resource,
+    /// a shutdown may complete without being able to recover the resource.
+    pub fn reap_expired(&self) -> usize {
+        let reaped = self.inner.leases.reap();
+        if reaped > 0 {
+            self.inner.raw.release_n(reaped);
+        }
+        reaped
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to access the underlying resource of this semaphore,
+    /// using the given strategy to decide how to proceed while it is out of capacity.
+    ///
+    /// When the strategy gives up, `TryAccessError::NoCapacity` will be returned.
+    /// If the semaphore is shut down, `TryAccessError::Shutdown` will be returned.
+    pub fn access_with<S: AcquireStrategy>(&self, mut strategy: S) -> TryAccessResult<T> {
+        loop {
+            if self.inner.raw.is_closed() {
+                return Err(self.inner.raw.deny(TryAccessError::Shutdown));
+            }
+            if self.inner.raw.is_poisoned() {
+                return Err(self.inner.raw.deny(TryAccessError::Poisoned));
+            }
+            if let Some(ref resource) = *self.inner.resource.read() {
+                if let Some(active) = self.inner.raw.try_acquire_n(1) {
+                    return Ok(guard::acquired(&self.inner.raw, resource, 1, active));
+                }
             } else {
-                Err(TryAccessError::NoCapacity)
+                return Err(self.inner.raw.deny(TryAccessError::Shutdown));
             }
-        } else {
-            Err(TryAccessError::Shutdown)
+            if !strategy.retry(&self.inner.raw) {
+                return Err(self.inner.raw.deny(TryAccessError::NoCapacity));
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore, blocking the current thread
+    /// until capacity becomes available.
+    ///
+    /// Unlike `access`, fair acquirers are granted access in the order in which they arrived.
+    /// Access released while fair acquirers are queued is handed to them directly, so non-fair
+    /// acquisitions such as `try_access` can not barge ahead of them. Note that non-fair
+    /// acquisitions can still take capacity that was added through `add_permits`, or that
+    /// was available before the fair acquirers arrived.
+    ///
+    /// If the semaphore has a capacity of zero, `AcquireError::NoCapacity` is returned right away.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access_fair(&self) -> AcquireResult<T> {
+        self.access_fair_until(None)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore in arrival order, blocking
+    /// the current thread until capacity becomes available or the timeout expires.
+    ///
+    /// An acquirer that times out leaves the queue, so it does not hold up the acquirers
+    /// behind it. If capacity becomes available just as the timeout expires, access is
+    /// still granted.
+    ///
+    /// If the timeout expires, `AcquireError::Timeout` will be returned.
+    /// If the semaphore has a capacity of zero, `AcquireError::NoCapacity` is returned right away.
+    /// If the semaphore is shut down, `AcquireError::Shutdown` will be returned.
+    pub fn access_fair_timeout(&self, timeout: Duration) -> AcquireResult<T> {
+        self.access_fair_until(Some(Instant::now() + timeout))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    fn access_fair_until(&self, deadline: Option<Instant>) -> AcquireResult<T> {
+        if self.inner.raw.is_closed() {
+            return Err(AcquireError::Shutdown);
+        }
+        if self.inner.raw.is_poisoned() {
+            return Err(AcquireError::Poisoned);
+        }
+        if self.inner.raw.capacity() == 0 && !self.inner.raw.is_gated() {
+            return Err(AcquireError::NoCapacity);
+        }
+        if !self.inner.raw.acquire_fair(deadline) {
+            if self.inner.raw.is_closed() {
+                return Err(AcquireError::Shutdown);
+            }
+            return Err(AcquireError::Timeout);
+        }
+        if !self.inner.raw.is_closed() {
+            if let Some(ref resource) = *self.inner.resource.read() {
+                return Ok(guard::new(&self.inner.raw, resource));
+            }
+        }
+        self.inner.raw.release();
+        Err(AcquireError::Shutdown)
+    }
+
+    #[cfg(feature = "fairness-trace")]
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource of this semaphore in arrival order, like `access_fair`,
+    /// recording `acquirer` in the fairness trace once access is granted.
+    ///
+    /// Only available with the `fairness-trace` feature. The acquirer id is chosen by the
+    /// caller, typically one per thread, so that `fairness_trace` can be used to tell how
+    /// evenly access was granted among them.
+    pub fn access_fair_traced(&self, acquirer: usize) -> AcquireResult<T> {
+        let guard = self.access_fair_until(None)?;
+        self.inner.raw.trace_grant(acquirer);
+        Ok(guard)
+    }
+
+    /// Join the queue of fair acquirers, returning a ticket to wait for access with later.
+    ///
+    /// The ticket reports its position in the queue through `WaitTicket::position`, for
+    /// example to tell a user how many are ahead of them, and is turned into a guard through
+    /// `WaitTicket::wait`. Until then, it holds up the fair acquirers queued behind it.
+    pub fn enqueue(&self) -> WaitTicket<'_, T> {
+        ticket::new(self)
+    }
+
+    /// Block the current thread until this semaphore has capacity available, without acquiring it.
+    ///
+    /// Returns immediately if the semaphore is not at capacity, or has been shut down.
+    /// Capacity is not reserved, so this only signals that capacity was available at some
+    /// point: a racing acquirer may take it before a subsequent `try_access` does.
+    pub fn wait_for_capacity(&self) {
+        self.inner.raw.wait()
+    }
+
+    /// Block the current thread until at least `k` accesses of this semaphore are available
+    /// at the same time, without acquiring any of them.
+    ///
+    /// Returns immediately if `k` accesses are available already, or the semaphore has been
+    /// shut down. Like `wait_for_capacity`, this is edge triggered: nothing is reserved, so
+    /// racing acquirers may have taken some of the accesses by the time this returns.
+    /// If `k` exceeds the capacity, this blocks until the capacity has grown accordingly.
+    pub fn wait_for_available(&self, k: usize) {
+        self.inner.raw.wait_for_available(k, None);
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource of this semaphore from within a poll-based
+    /// state machine, registering the current task for wakeup if it is out of capacity.
+    ///
+    /// Returns `Poll::Pending` if the semaphore is out of capacity, in which case the waker
+    /// of the given context is woken by the next release, or when the semaphore is closed.
+    /// Each registration is woken at most once, so the task needs to poll again to stay
+    /// registered. Polling repeatedly with wakers of the same task registers it only once.
+    ///
+    /// There is no explicit cancellation: a task that stops polling simply stays registered
+    /// until the next release, and is then woken spuriously. No capacity is held while pending.
+    pub fn poll_acquire(&self, cx: &mut Context) -> Poll<TryAccessResult<T>> {
+        match self.try_access() {
+            Err(TryAccessError::NoCapacity) => {},
+            result => return Poll::Ready(result)
+        }
+        self.inner.raw.register_task(cx.waker());
+        // Check again now that the waker is registered, so that a release
+        // racing with the registration is not missed.
+        match self.try_access() {
+            Err(TryAccessError::NoCapacity) => Poll::Pending,
+            result => Poll::Ready(result)
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Asynchronously access the underlying resource of this semaphore.
+    ///
+    /// Returns a future that resolves to an RAII guard once capacity becomes available,
+    /// or to `TryAccessError::Shutdown` if the semaphore is shut down.
+    /// Dropping the future cancels the acquisition without consuming any capacity.
+    pub fn acquire_async(&self) -> AcquireFuture<T> {
+        future::new(self)
+    }
+
+    #[cfg(feature = "async")]
+    /// Create a stream that yields a guard each time capacity becomes available.
+    ///
+    /// Useful for async producers that should only emit work as fast as it can be
+    /// processed: pull a guard, do the work, and the next guard arrives as capacity returns.
+    /// The stream ends when the semaphore is shut down.
+    /// Dropping the stream cancels any pending acquisition.
+    pub fn permit_stream(&self) -> PermitStream<T> {
+        future::stream(self)
+    }
+
+    /// Create a view of this semaphore that hands out a value derived from the resource.
+    ///
+    /// The view shares capacity and shutdown state with this semaphore.
+    /// The mapping function runs once per acquisition through the view, not once up front.
+    pub fn map<U, F>(&self, f: F) -> MappedSemaphore<T, U>
+        where F: Fn(&T) -> U + Send + Sync + 'static
+    {
+        mapped::new(self, Arc::new(f))
+    }
+
+    #[inline]
+    /// Maximum number of accesses this semaphore grants at the same time.
+    pub fn capacity(&self) -> usize {
+        self.inner.raw.capacity()
+    }
+
+    #[inline]
+    /// Fraction of the capacity that is currently held, usually between `0.0` and `1.0`.
+    ///
+    /// Exceeds `1.0` while more access is held than a capacity shrunk by `remove_permits`
+    /// allows. A semaphore without any capacity is considered fully utilized.
+    pub fn utilization(&self) -> f64 {
+        let capacity = self.inner.raw.capacity();
+        if capacity == 0 {
+            return 1.0;
         }
+        self.inner.raw.active() as f64 / capacity as f64
+    }
+
+    #[inline]
+    /// Number of accesses to this semaphore that are currently held.
+    ///
+    /// Under correct usage this equals the number of live guards, with a guard from
+    /// `try_access_n` counting once for each of its accesses. The value may be stale
+    /// by the time it is returned if other threads are acquiring or releasing concurrently.
+    pub fn active_count(&self) -> usize {
+        self.inner.raw.active()
+    }
+
+    /// Read the capacity, active count and other state of this semaphore together.
+    ///
+    /// Unlike separate calls to `capacity` and `active_count`, the snapshot is consistent,
+    /// see `SemaphoreSnapshot` for the exact guarantees. It is meant for monitoring,
+    /// and may be stale by the time it is returned.
+    pub fn snapshot(&self) -> SemaphoreSnapshot {
+        self.inner.raw.snapshot()
+    }
+
+    /// Create a weak handle to this semaphore, which does not keep it alive.
+    ///
+    /// Used by `SemaphoreRegistry` to track semaphores without extending their lifetime.
+    pub fn downgrade(&self) -> WeakSemaphore<T> {
+        weak::new(self)
+    }
+
+    #[inline]
+    /// Number of handles that share ownership of this semaphore, including this one.
+    ///
+    /// Besides clones of the semaphore, every live guard, permit guard, shutdown handle
+    /// and mapped view holds on to the semaphore as well, and is included in the count.
+    /// Once all of those have been dropped, this returns `1`.
+    pub fn handle_count(&self) -> usize {
+        // Every clone shares the single reference held by the shared state.
+        Arc::strong_count(&self.inner) + Arc::strong_count(&self.inner.raw) - 1
+    }
+
+    #[cfg(feature = "stats")]
+    #[inline]
+    /// Highest number of accesses that have been held at the same time.
+    ///
+    /// Only available with the `stats` feature. Tracks the peak since the semaphore
+    /// was created, or since the last call to `reset_peak`.
+    pub fn peak_active(&self) -> usize {
+        self.inner.raw.peak_active()
+    }
+
+    #[cfg(feature = "stats")]
+    #[inline]
+    /// Reset the peak reported by `peak_active` to the number of accesses currently held.
+    pub fn reset_peak(&self) {
+        self.inner.raw.reset_peak()
+    }
+
+    #[cfg(feature = "stats")]
+    /// Summary of how long blocked acquirers had to wait before being granted access.
+    ///
+    /// Only available with the `stats` feature. Covers `access`, `access_timeout`
+    /// and `access_n_timeout`, counting only acquisitions that had to block.
+    pub fn wait_stats(&self) -> WaitStats {
+        self.inner.raw.wait_stats()
+    }
+
+    #[cfg(feature = "throughput")]
+    /// Recent rate of acquisitions and releases, in events per second.
+    ///
+    /// Only available with the `throughput` feature. Averages over the last
+    /// `THROUGHPUT_WINDOW_SECS` seconds, telling a saturated semaphore that still makes
+    /// progress apart from one that is stalled. Each acquisition or release counts as
+    /// one event, regardless of how many accesses it covers.
+    pub fn recent_throughput(&self) -> (f64, f64) {
+        self.inner.raw.recent_throughput()
+    }
+
+    #[cfg(feature = "fairness-trace")]
+    /// Acquirer ids recorded by `access_fair_traced`, in the order in which they were granted access.
+    ///
+    /// Only available with the `fairness-trace` feature. Holds the most recent
+    /// `FAIRNESS_TRACE_LEN` grants, older ones are discarded.
+    pub fn fairness_trace(&self) -> Vec<usize> {
+        self.inner.raw.fairness_trace()
+    }
+
+    #[cfg(feature = "debug-deadlock")]
+    /// Set how long blocking acquisitions wait before reporting a probable deadlock.
+    ///
+    /// Only available with the `debug-deadlock` feature, and only effective in builds
+    /// with debug assertions. An acquisition through `access` and its variants that blocks
+    /// for longer than the threshold prints a warning to standard error, with the number of
+    /// accesses held and, with the `debug-holders` feature, where they were acquired.
+    /// It then keeps waiting as before. The threshold defaults to ten seconds.
+    pub fn set_deadlock_threshold(&self, threshold: Duration) {
+        self.inner.raw.set_deadlock_threshold(threshold)
+    }
+
+    /// Set for how many waiters storage is kept around once a burst of waiters has drained.
+    ///
+    /// Blocked acquirers and registered wakers are kept in growable storage. Once that storage
+    /// is at most a quarter full, it is shrunk again, but never below the given number of
+    /// waiters, so that a burst of waiters does not leave its peak allocation behind.
+    /// Defaults to 32 waiters.
+    pub fn set_retained_waiters(&self, retained: usize) {
+        self.inner.raw.set_retained_waiters(retained)
+    }
+
+    #[cfg(feature = "debug-holders")]
+    /// List the acquisition sites of all guards that are currently held, oldest first.
+    ///
+    /// Only available with the `debug-holders` feature, which records the caller
+    /// location of every successful acquisition.
+    pub fn outstanding(&self) -> Vec<&'static Location<'static>> {
+        self.inner.raw.holders().sites()
+    }
+
+    #[cfg(feature = "debug-holders")]
+    /// List the guards that have been held for longer than `older_than`, oldest first.
+    ///
+    /// Only available with the `debug-holders` feature. Meant for finding leaked access,
+    /// such as a guard held across an `.await` that never resolves: each entry carries the
+    /// acquisition site, the tag if the guard has one, and when it was acquired. Guards
+    /// from `acquire_async` can be tagged through `AcquireFuture::tagged`.
+    pub fn diagnose_stuck(&self, older_than: Duration) -> Vec<GuardInfo> {
+        self.inner.raw.holders().stuck(older_than)
     }
 
     /// Shut down the semaphore.
     ///
-    /// This prevents any further access from being granted to the underlying resource.
+    /// This prevents any further access from being granted to the underlying resource,
+    /// and wakes up all blocked acquirers, like `close` does.
     /// As soon as the last access is released and the returned handle goes out of scope,
     /// the resource will be dropped.
     ///
     /// Does _not_ block until the resource is no longer in use. If you would like to do that,
     /// you can call `wait` on the returned handle.
+    ///
+    /// Closing the semaphore and taking the resource happen atomically with respect to
+    /// other calls to `shutdown` from any clone, so exactly one of them receives a handle
+    /// holding the resource, which it reports through `ShutdownHandle::won_resource`.
+    /// Use `shutdown_initiated` to check whether that has happened.
     pub fn shutdown(&self) -> ShutdownHandle<T> {
-        shutdown::new(&self.raw, self.resource.write().take())
+        let mut resource = self.inner.resource.write();
+        self.inner.raw.close();
+        shutdown::new(&self.inner.raw, resource.take())
+    }
+
+    /// Get a reference to the underlying resource, without acquiring access.
+    ///
+    /// This bypasses the capacity limit entirely, so it should only be used for reads that
+    /// are safe regardless of how many users access the resource at the same time.
+    /// Returns `None` if the semaphore has been shut down, or while a guard is mutably
+    /// projected through `SemaphoreGuard::map_mut`. While the returned `Arc` is alive,
+    /// `ShutdownHandle::wait` can not extract the resource and returns `None`,
+    /// use `ShutdownHandle::wait_arc` instead.
+    pub fn resource(&self) -> Option<Arc<T>> {
+        let resource = self.inner.resource.read().clone()?;
+        if self.inner.raw.is_projected() {
+            return None;
+        }
+        Some(resource)
+    }
+
+    /// Turn this into a semaphore that can not be cloned, if this is its only handle.
+    ///
+    /// Succeeds only if there are no clones of this semaphore, including those held
+    /// by mapped views or pending futures, and no weak handles to it, including those held
+    /// by a `SemaphoreRegistry`. Guards may still be held. Otherwise the semaphore is
+    /// handed back unchanged.
+    pub fn try_into_owned(self) -> Result<OwnedSemaphore<T>, Semaphore<T>> {
+        // Weak handles could be upgraded to new clones of the owned semaphore.
+        if Arc::strong_count(&self.inner) != 1 || Arc::weak_count(&self.inner) != 0 {
+            return Err(self);
+        }
+        Ok(owned::new(self))
+    }
+
+    /// Grow the capacity of this semaphore by `k`, up to at most `usize::MAX`.
+    ///
+    /// Blocked acquirers are woken only as far as the new capacity can satisfy them,
+    /// so growing by one wakes a single acquirer of one access, however many are waiting.
+    /// While a guard is projected through `SemaphoreGuard::map_mut`, the growth is
+    /// deferred until the projection is released.
+    pub fn add_permits(&self, k: usize) {
+        self.inner.raw.add_permits(k)
+    }
+
+    /// Shrink the capacity of this semaphore by `k`, down to at most zero.
+    ///
+    /// Guards that are already held stay valid, even if more access is now held than the new
+    /// capacity allows. No further access is granted until enough of them have been released.
+    pub fn remove_permits(&self, k: usize) {
+        self.inner.raw.remove_permits(k)
+    }
+
+    /// Donate up to `n` units of capacity of this semaphore to `to`, until the returned
+    /// donation is reclaimed or dropped.
+    ///
+    /// This changes the limits of both semaphores, not their active counts: the capacity of
+    /// this semaphore shrinks, at most down to zero, and that of `to` grows by the same amount.
+    /// Guards held on either semaphore are not affected. Useful to lend the allowance of an
+    /// idle worker to a busy one, such as in a work-stealing scheduler.
+    pub fn donate(&self, to: &Semaphore<T>, n: usize) -> Donation {
+        donation::new(&self.inner.raw, &to.inner.raw, n)
+    }
+
+    /// Open this semaphore as a gate, letting up to `n` accesses through at the same time.
+    ///
+    /// Sets the capacity to `n`, waking as many blocked acquirers as it can satisfy.
+    /// Unlike a shutdown, the gate can be closed and opened again any number of times.
+    /// Once a semaphore has been used as a gate, blocking acquisitions wait for it to open
+    /// instead of failing with `AcquireError::NoCapacity` while its capacity is zero.
+    pub fn open(&self, n: usize) {
+        self.inner.raw.set_capacity(n)
+    }
+
+    /// Close this semaphore as a gate, by setting its capacity to zero.
+    ///
+    /// Guards that are already held stay valid, but no further access is granted
+    /// until the gate is opened again through `open`.
+    pub fn close_gate(&self) {
+        self.inner.raw.set_capacity(0)
+    }
+
+    #[inline]
+    /// Whether a thread panicked while holding access to this semaphore,
+    /// if it was created through `with_poisoning`.
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.raw.is_poisoned()
+    }
+
+    /// Clear the poisoned state of this semaphore, so that it grants access again.
+    pub fn clear_poison(&self) {
+        self.inner.raw.clear_poison()
+    }
+
+    #[inline]
+    /// Whether `shutdown` has been called on this semaphore or any of its clones.
+    ///
+    /// Unlike being closed, this means that the resource has been handed to a shutdown handle.
+    pub fn shutdown_initiated(&self) -> bool {
+        self.inner.resource.read().is_none()
+    }
+
+    /// Close the semaphore.
+    ///
+    /// This prevents any further access from being granted, and wakes up all acquirers
+    /// that are currently blocked or pending, which will then fail with `TryAccessError::Shutdown`.
+    /// Access that was granted before is not affected.
+    ///
+    /// Unlike `shutdown`, closing leaves the resource in place, so it can still be extracted
+    /// through a subsequent call to `shutdown`.
+    pub fn close(&self) {
+        self.inner.raw.close();
     }
 }
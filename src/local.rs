@@ -0,0 +1,106 @@
+use std::cell::Cell;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use super::TryAccessError;
+
+/// Counting semaphore for use within a single thread.
+///
+/// Keeps track of active accesses through a plain `Cell` instead of atomics, so acquiring
+/// and releasing access costs no synchronization at all. In exchange, neither the semaphore
+/// nor its guards are thread-safe: they can not be sent to or shared with other threads,
+/// much like `Rc` and `RefCell` compared to `Arc` and `Mutex`. This suits single-threaded
+/// async runtimes, where tasks are spawned through functions like `spawn_local`.
+///
+/// ```compile_fail
+/// # use semaphore::LocalSemaphore;
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// assert_sync(&LocalSemaphore::new(1, ()));
+/// ```
+///
+/// Like `Semaphore`, clones share the same capacity, and guards own their access.
+/// A local semaphore can not be shut down.
+pub struct LocalSemaphore<T: ?Sized> {
+    inner: Rc<Inner<T>>
+}
+
+struct Inner<T: ?Sized> {
+    active: Cell<usize>,
+    capacity: usize,
+    resource: T
+}
+
+impl<T: ?Sized> Clone for LocalSemaphore<T> {
+    fn clone(&self) -> LocalSemaphore<T> {
+        LocalSemaphore {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+impl<T> LocalSemaphore<T> {
+    /// Create a new local semaphore around a resource.
+    ///
+    /// The semaphore will limit the number of guards that can access
+    /// the underlying resource at the same time to the specified capacity.
+    pub fn new(capacity: usize, resource: T) -> Self {
+        LocalSemaphore {
+            inner: Rc::new(Inner {
+                active: Cell::new(0),
+                capacity,
+                resource
+            })
+        }
+    }
+}
+
+impl<T: ?Sized> LocalSemaphore<T> {
+    /// Attempt to access the underlying resource of this semaphore.
+    ///
+    /// If the semaphore is out of capacity, `TryAccessError::NoCapacity` will be returned.
+    pub fn try_access(&self) -> Result<LocalGuard<T>, TryAccessError> {
+        let active = self.inner.active.get();
+        if active >= self.inner.capacity {
+            return Err(TryAccessError::NoCapacity);
+        }
+        self.inner.active.set(active + 1);
+        Ok(LocalGuard {
+            inner: self.inner.clone()
+        })
+    }
+
+    #[inline]
+    /// Maximum number of accesses this semaphore grants at the same time.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    #[inline]
+    /// Number of accesses that are currently held.
+    pub fn active_count(&self) -> usize {
+        self.inner.active.get()
+    }
+}
+
+/// RAII guard releasing access to a `LocalSemaphore` when it falls out of scope.
+///
+/// Returned from `LocalSemaphore::try_access`.
+pub struct LocalGuard<T: ?Sized> {
+    inner: Rc<Inner<T>>
+}
+
+impl<T: ?Sized> Drop for LocalGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.inner.active.set(self.inner.active.get() - 1);
+    }
+}
+
+impl<T: ?Sized> Deref for LocalGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner.resource
+    }
+}
@@ -0,0 +1,76 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::{AcquireError, Semaphore, SemaphoreGuard, TryAccessError};
+
+/// View of a semaphore that hands out a value derived from its resource.
+///
+/// Returned from `Semaphore::map`. The view shares capacity and shutdown state with
+/// the semaphore it was created from, so every guard it hands out counts against
+/// the capacity of the parent semaphore.
+///
+/// The mapping function runs once per successful acquisition, right after access
+/// has been acquired, and its result lives as long as the guard.
+pub struct MappedSemaphore<T: ?Sized, U> {
+    semaphore: Semaphore<T>,
+    map: Arc<dyn Fn(&T) -> U + Send + Sync>
+}
+
+pub fn new<T: ?Sized, U>(semaphore: &Semaphore<T>, map: Arc<dyn Fn(&T) -> U + Send + Sync>) -> MappedSemaphore<T, U> {
+    MappedSemaphore {
+        semaphore: semaphore.clone(),
+        map
+    }
+}
+
+impl<T: ?Sized, U> Clone for MappedSemaphore<T, U> {
+    fn clone(&self) -> MappedSemaphore<T, U> {
+        MappedSemaphore {
+            semaphore: self.semaphore.clone(),
+            map: self.map.clone()
+        }
+    }
+}
+
+impl<T: ?Sized, U> MappedSemaphore<T, U> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the derived value.
+    ///
+    /// Fails in the same way as `Semaphore::try_access` on the parent semaphore.
+    pub fn try_access(&self) -> Result<MappedGuard<T, U>, TryAccessError> {
+        self.semaphore.try_access().map(|guard| self.wrap(guard))
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the derived value, blocking the current thread until capacity becomes available.
+    ///
+    /// Fails in the same way as `Semaphore::access` on the parent semaphore.
+    pub fn access(&self) -> Result<MappedGuard<T, U>, AcquireError> {
+        self.semaphore.access().map(|guard| self.wrap(guard))
+    }
+
+    fn wrap(&self, guard: SemaphoreGuard<T>) -> MappedGuard<T, U> {
+        MappedGuard {
+            value: (self.map)(&guard),
+            _guard: guard
+        }
+    }
+}
+
+/// RAII guard holding access to a semaphore together with a value derived from its resource.
+///
+/// Returned from `MappedSemaphore::try_access`. The derived value is dropped
+/// before the access is released.
+pub struct MappedGuard<T: ?Sized, U> {
+    value: U,
+    _guard: SemaphoreGuard<T>
+}
+
+impl<T: ?Sized, U> Deref for MappedGuard<T, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &U {
+        &self.value
+    }
+}
@@ -0,0 +1,21 @@
+use super::TryAccessError;
+
+/// Receiver of the lifecycle events of a semaphore, for instrumentation.
+///
+/// Registered through `Semaphore::with_observer`. Events are delivered synchronously
+/// on the thread that caused them, so implementations should be cheap and must not
+/// access the semaphore they observe. All methods do nothing by default.
+pub trait SemaphoreObserver: Send + Sync {
+    /// Called after access has been acquired, with the number of accesses now active.
+    fn on_acquire(&self, _active: usize) {}
+
+    /// Called after access has been released, with the number of accesses still active.
+    fn on_release(&self, _active: usize) {}
+
+    /// Called when an attempt to acquire access through `try_access`, one of its variants,
+    /// or `access_with` fails.
+    fn on_denied(&self, _reason: TryAccessError) {}
+
+    /// Called once, when the semaphore is closed or shut down.
+    fn on_shutdown(&self) {}
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{AcquireResult, Semaphore, TryAccessResult};
+
+/// Semaphore that is guaranteed to have a single owner.
+///
+/// Returned from `Semaphore::try_into_owned`. Unlike `Semaphore`, it can not be cloned,
+/// which allows it to hand out mutable access to the resource while no guards are held.
+pub struct OwnedSemaphore<T: ?Sized> {
+    semaphore: Semaphore<T>
+}
+
+pub fn new<T: ?Sized>(semaphore: Semaphore<T>) -> OwnedSemaphore<T> {
+    OwnedSemaphore { semaphore }
+}
+
+impl<T: ?Sized> OwnedSemaphore<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to access the underlying resource, like `Semaphore::try_access`.
+    pub fn try_access(&self) -> TryAccessResult<T> {
+        self.semaphore.try_access()
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource, blocking like `Semaphore::access`.
+    pub fn access(&self) -> AcquireResult<T> {
+        self.semaphore.access()
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Access the underlying resource, blocking like `Semaphore::access_timeout`.
+    pub fn access_timeout(&self, timeout: Duration) -> AcquireResult<T> {
+        self.semaphore.access_timeout(timeout)
+    }
+
+    /// Get mutable access to the underlying resource.
+    ///
+    /// Returns `None` while any guard is held, if the resource is still shared through
+    /// `Semaphore::from_arc` or `Semaphore::resource`, or after a shutdown.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        let inner = Arc::get_mut(&mut self.semaphore.inner)?;
+        inner.resource.get_mut().as_mut().and_then(Arc::get_mut)
+    }
+
+    /// Turn this back into a semaphore that can be cloned.
+    pub fn into_shared(self) -> Semaphore<T> {
+        self.semaphore
+    }
+}
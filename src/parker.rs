@@ -0,0 +1,205 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::Wake;
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+/// Parking spot of a single blocked acquirer.
+#[derive(Default)]
+pub struct Parker {
+    notified: Mutex<bool>,
+    interrupted: AtomicBool,
+    cond: Condvar
+}
+
+impl Parker {
+    /// Block until notified or interrupted, or until the deadline passes.
+    ///
+    /// Returns whether the parker has been notified.
+    pub fn park(&self, deadline: Option<Instant>) -> bool {
+        let mut notified = self.notified.lock();
+        while !*notified && !self.interrupted.load(Ordering::SeqCst) {
+            match deadline {
+                Some(deadline) => {
+                    if self.cond.wait_until(&mut notified, deadline).timed_out() {
+                        break;
+                    }
+                },
+                None => {
+                    self.cond.wait(&mut notified);
+                }
+            }
+        }
+        *notified
+    }
+
+    #[cfg(all(feature = "debug-deadlock", debug_assertions))]
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    fn unpark(&self) {
+        *self.notified.lock() = true;
+        self.cond.notify_one();
+    }
+
+    /// Wake the parked acquirer without handing it any capacity.
+    fn interrupt(&self) {
+        let _notified = self.notified.lock();
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.cond.notify_one();
+    }
+}
+
+/// Waking a parker interrupts it, so that it can observe a cancellation.
+impl Wake for Parker {
+    fn wake(self: Arc<Self>) {
+        self.interrupt()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.interrupt()
+    }
+}
+
+/// Number of waiters whose storage is retained by default once a burst of waiters has drained.
+pub const DEFAULT_RETAINED_WAITERS: usize = 32;
+
+/// Capacity to shrink storage for waiters with `len` entries to, once it has become mostly empty.
+///
+/// Storage is only shrunk once at most a quarter of it is in use, and never below `retained`,
+/// so that a steady number of waiters does not reallocate it over and over.
+pub fn reclaim_target(len: usize, capacity: usize, retained: usize) -> Option<usize> {
+    if capacity > retained && len <= capacity / 4 {
+        Some(cmp::max(len * 2, retained))
+    } else {
+        None
+    }
+}
+
+/// First-in-first-out queue of blocked acquirers, each parked on its own parker,
+/// so that a release only wakes as many acquirers as it can satisfy.
+pub struct WaitQueue {
+    len: AtomicUsize,
+    entries: Mutex<VecDeque<(usize, Arc<Parker>)>>,
+    retained: AtomicUsize
+}
+
+impl Default for WaitQueue {
+    fn default() -> WaitQueue {
+        WaitQueue {
+            len: AtomicUsize::default(),
+            entries: Mutex::default(),
+            retained: AtomicUsize::new(DEFAULT_RETAINED_WAITERS)
+        }
+    }
+}
+
+impl WaitQueue {
+    /// Number of waiters whose storage is kept when the queue drains, shared with
+    /// the other waiter storage of the semaphore.
+    #[inline]
+    pub fn retained(&self) -> usize {
+        self.retained.load(Ordering::Relaxed)
+    }
+
+    pub fn set_retained(&self, retained: usize) {
+        self.retained.store(retained, Ordering::Relaxed);
+        self.reclaim(&mut self.entries.lock());
+    }
+
+    fn reclaim(&self, entries: &mut VecDeque<(usize, Arc<Parker>)>) {
+        if let Some(target) = reclaim_target(entries.len(), entries.capacity(), self.retained()) {
+            entries.shrink_to(target);
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len.load(Ordering::SeqCst) == 0
+    }
+
+    /// Enqueue a parker for an acquirer of the given number of accesses.
+    ///
+    /// Acquirers that were woken but lost the race for capacity re-enter
+    /// at the front, so that they keep their position.
+    pub fn push(&self, permits: usize, parker: &Arc<Parker>, front: bool) {
+        let mut entries = self.entries.lock();
+        if front {
+            entries.push_front((permits, parker.clone()));
+        } else {
+            entries.push_back((permits, parker.clone()));
+        }
+        self.len.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Remove a parker that has not been woken yet.
+    ///
+    /// Returns `false` if the parker was already dequeued to be woken.
+    pub fn remove(&self, parker: &Arc<Parker>) -> bool {
+        let mut entries = self.entries.lock();
+        match entries.iter().position(|entry| Arc::ptr_eq(&entry.1, parker)) {
+            Some(index) => {
+                entries.remove(index);
+                self.len.fetch_sub(1, Ordering::SeqCst);
+                self.reclaim(&mut entries);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Wake acquirers from the front of the queue, for as long as the given number
+    /// of available accesses covers their demand.
+    ///
+    /// An acquirer at the front that needs more than is available blocks the ones behind it,
+    /// so that acquirers of many accesses are not starved by acquirers of few.
+    pub fn wake(&self, mut available: usize) {
+        let woken = {
+            let mut entries = self.entries.lock();
+            let mut woken = Vec::new();
+            while let Some(&(permits, _)) = entries.front() {
+                if permits > available {
+                    break;
+                }
+                available -= permits;
+                woken.extend(entries.pop_front().map(|entry| entry.1));
+            }
+            self.len.fetch_sub(woken.len(), Ordering::SeqCst);
+            if !woken.is_empty() {
+                self.reclaim(&mut entries);
+            }
+            woken
+        };
+        for parker in woken {
+            parker.unpark();
+        }
+    }
+
+    /// Wake all acquirers, so that they can observe the semaphore being closed.
+    pub fn wake_all(&self) {
+        let woken = {
+            let mut entries = self.entries.lock();
+            self.len.fetch_sub(entries.len(), Ordering::SeqCst);
+            let woken = entries.drain(..).collect::<Vec<_>>();
+            self.reclaim(&mut entries);
+            woken
+        };
+        for (_, parker) in woken {
+            parker.unpark();
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub fn storage_capacity(&self) -> usize {
+        self.entries.lock().capacity()
+    }
+}
@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Weak};
+
+use guard;
+use raw::RawSemaphore;
+use super::{AcquireError, AcquireResult, SemaphoreGuard, TryAccessError, TryAccessResult};
+
+/// Guard that has released its access for the time being, to acquire it again later.
+///
+/// Returned from `SemaphoreGuard::pause`. Resuming acquires as many accesses as the
+/// guard held before, and carries over its tag and priority, but never its lease.
+/// Dropping a paused guard without resuming it does nothing.
+pub struct PausedGuard<T: ?Sized> {
+    raw: Arc<RawSemaphore>,
+    resource: Weak<T>,
+    permits: usize,
+    tag: Option<Cow<'static, str>>,
+    priority: u32
+}
+
+pub fn new<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>, permits: usize, tag: Option<Cow<'static, str>>, priority: u32) -> PausedGuard<T> {
+    PausedGuard {
+        raw: raw.clone(),
+        resource: Arc::downgrade(resource),
+        permits,
+        tag,
+        priority
+    }
+}
+
+impl<T: ?Sized> PausedGuard<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to acquire the access of this guard again, without blocking.
+    ///
+    /// Fails in the same way as `Semaphore::try_access_n`, in which case the paused guard
+    /// is consumed. Use `resume_blocking` to wait for the access to become available.
+    pub fn resume(self) -> TryAccessResult<T> {
+        let resource = match self.resource.upgrade() {
+            Some(resource) => resource,
+            None => return Err(TryAccessError::Shutdown)
+        };
+        let mut guard = guard::try_reacquire(&self.raw, &resource, self.permits)?;
+        self.restore(&mut guard);
+        Ok(guard)
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Acquire the access of this guard again, blocking until it is available.
+    ///
+    /// Fails with `AcquireError::Shutdown` if the semaphore is shut down in the meantime,
+    /// or with `AcquireError::Poisoned` if it has been poisoned. Fails with
+    /// `AcquireError::NoCapacity` if the capacity is shrunk below the paused accesses.
+    pub fn resume_blocking(self) -> AcquireResult<T> {
+        if self.raw.is_closed() {
+            return Err(AcquireError::Shutdown);
+        }
+        if self.raw.is_poisoned() {
+            return Err(AcquireError::Poisoned);
+        }
+        if !self.raw.acquire_blocking(self.permits, None, None) {
+            if self.raw.exceeds_capacity(self.permits) {
+                return Err(AcquireError::NoCapacity);
+            }
+            return Err(AcquireError::Shutdown);
+        }
+        // The resource is gone once a shutdown has completed.
+        match self.resource.upgrade() {
+            Some(ref resource) if !self.raw.is_closed() => {
+                let mut guard = guard::with_permits(&self.raw, resource, self.permits);
+                self.restore(&mut guard);
+                Ok(guard)
+            },
+            _ => {
+                self.raw.release_n(self.permits);
+                Err(AcquireError::Shutdown)
+            }
+        }
+    }
+
+    fn restore(&self, guard: &mut SemaphoreGuard<T>) {
+        guard::set_priority(guard, self.priority);
+        if let Some(ref tag) = self.tag {
+            guard::set_tag(guard, tag.clone());
+        }
+    }
+}
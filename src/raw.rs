@@ -1,64 +1,1190 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::{BTreeMap, VecDeque};
+use std::hint;
+use std::mem;
+use std::panic::Location;
+use std::sync::Arc;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::task::Waker;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use parking_lot::{Condvar, Mutex};
+use parking_lot::{Condvar, Mutex, MutexGuard};
 
+use cancel::Cancellable;
+use observer::SemaphoreObserver;
+#[cfg(feature = "debug-holders")]
+use holders::Holders;
+#[cfg(feature = "async")]
+use atomic_waker::AtomicWaker;
+use parker::{reclaim_target, Parker, WaitQueue};
+use snapshot::SemaphoreSnapshot;
+#[cfg(feature = "stats")]
+use stats::{WaitRecorder, WaitStats};
+#[cfg(feature = "fairness-trace")]
+use trace::FairnessTrace;
+#[cfg(feature = "throughput")]
+use throughput::Throughput;
+use super::TryAccessError;
+
+/// Number of consecutive failed compare-and-swap attempts after which an acquirer
+/// yields to the scheduler instead of only spinning.
+const YIELD_THRESHOLD: usize = 16;
+
+/// Time a blocked acquirer waits before reporting a probable deadlock, unless configured otherwise.
+#[cfg(feature = "debug-deadlock")]
+const DEFAULT_DEADLOCK_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Value of `RawSemaphore::slot_key` while no registration owns the waker slot.
+#[cfg(feature = "async")]
+const FREE_SLOT: usize = usize::MAX;
+
+/// Low-level counting primitive shared by a semaphore and its guards.
+///
+/// Handed to an `AcquireStrategy` so that custom strategies can wait
+/// for capacity to become available between acquisition attempts.
 pub struct RawSemaphore {
     active: AtomicUsize,
-    capacity: usize,
-    lock: Mutex<()>,
-    cond: Condvar
+    capacity: AtomicUsize,
+    /// Growth of the capacity held back while the resource is projected.
+    resize: Mutex<usize>,
+    closed: AtomicBool,
+    projected: AtomicBool,
+    /// Whether the capacity is toggled through `Semaphore::open` and `Semaphore::close_gate`.
+    gated: AtomicBool,
+    poisoning: AtomicBool,
+    poisoned: AtomicBool,
+    waiters: AtomicUsize,
+    /// Number of those waiters that are waiting for more than a single access to be available.
+    headroom_waiters: AtomicUsize,
+    queue: WaitQueue,
+    sequence: AtomicU64,
+    lock: Mutex<FairQueue>,
+    cond: Condvar,
+    completion: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    wakers: Mutex<Wakers>,
+    /// Waker of a single registration that is woken without taking the lock of `wakers`.
+    #[cfg(feature = "async")]
+    slot: AtomicWaker,
+    /// Key of the registration owning `slot`, or `FREE_SLOT`.
+    #[cfg(feature = "async")]
+    slot_key: AtomicUsize,
+    #[cfg(feature = "async")]
+    next_key: AtomicUsize,
+    /// Number of acquirers waiting with each priority, through `Semaphore::access_with_priority`.
+    priorities: Mutex<BTreeMap<u32, usize>>,
+    observer: Option<Box<dyn SemaphoreObserver>>,
+    /// Time a guard may be held for before its release is reported.
+    hold_threshold: Option<Duration>,
+    #[cfg(feature = "debug-holders")]
+    holders: Holders,
+    #[cfg(feature = "leak-detection")]
+    orphaned: AtomicBool,
+    #[cfg(feature = "stats")]
+    peak_active: AtomicUsize,
+    #[cfg(feature = "stats")]
+    waits: WaitRecorder,
+    #[cfg(feature = "debug-deadlock")]
+    deadlock_threshold: AtomicU64,
+    #[cfg(all(feature = "debug-deadlock", test))]
+    deadlock_reports: AtomicUsize,
+    #[cfg(feature = "fairness-trace")]
+    grants: FairnessTrace,
+    #[cfg(feature = "throughput")]
+    throughput: Throughput,
+    #[cfg(test)]
+    notifications: AtomicUsize,
+    #[cfg(test)]
+    long_holds: AtomicUsize,
+    /// Hook run right before a waker is registered, to interleave a release with a poll.
+    #[cfg(test)]
+    before_register: Mutex<Option<Box<dyn FnOnce() + Send>>>
+}
+
+/// Tickets of fair acquirers, in the order in which they are to be granted access.
+#[derive(Default)]
+struct FairQueue {
+    next_ticket: usize,
+    tickets: VecDeque<usize>,
+    /// Number of accesses handed directly to the tickets at the front of the queue.
+    handed: usize
+}
+
+impl FairQueue {
+    fn remove(&mut self, position: usize, retained: usize) {
+        self.tickets.remove(position);
+        if let Some(target) = reclaim_target(self.tickets.len(), self.tickets.capacity(), retained) {
+            self.tickets.shrink_to(target);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Wakers {
+    entries: Vec<(usize, Waker)>,
+    /// Wakers registered without a key, which can not be deregistered.
+    tasks: Vec<Waker>
 }
 
 impl RawSemaphore {
-    pub fn new(capacity: usize) -> RawSemaphore {
+    pub(crate) fn new(capacity: usize) -> RawSemaphore {
+        RawSemaphore::with_observer(capacity, None)
+    }
+
+    pub(crate) fn with_observer(capacity: usize, observer: Option<Box<dyn SemaphoreObserver>>) -> RawSemaphore {
         RawSemaphore {
             active: AtomicUsize::default(),
-            capacity: capacity,
-            lock: Mutex::new(()),
-            cond: Condvar::new()
+            capacity: AtomicUsize::new(capacity),
+            resize: Mutex::new(0),
+            closed: AtomicBool::new(false),
+            projected: AtomicBool::new(false),
+            gated: AtomicBool::new(false),
+            poisoning: AtomicBool::new(false),
+            poisoned: AtomicBool::new(false),
+            waiters: AtomicUsize::default(),
+            headroom_waiters: AtomicUsize::default(),
+            queue: WaitQueue::default(),
+            sequence: AtomicU64::default(),
+            lock: Mutex::new(FairQueue::default()),
+            cond: Condvar::new(),
+            completion: Mutex::new(None),
+            wakers: Mutex::new(Wakers::default()),
+            #[cfg(feature = "async")]
+            slot: AtomicWaker::new(),
+            #[cfg(feature = "async")]
+            slot_key: AtomicUsize::new(FREE_SLOT),
+            #[cfg(feature = "async")]
+            next_key: AtomicUsize::new(0),
+            priorities: Mutex::new(BTreeMap::new()),
+            observer,
+            hold_threshold: None,
+            #[cfg(feature = "debug-holders")]
+            holders: Holders::default(),
+            #[cfg(feature = "leak-detection")]
+            orphaned: AtomicBool::new(false),
+            #[cfg(feature = "stats")]
+            peak_active: AtomicUsize::default(),
+            #[cfg(feature = "stats")]
+            waits: WaitRecorder::default(),
+            #[cfg(feature = "debug-deadlock")]
+            deadlock_threshold: AtomicU64::new(DEFAULT_DEADLOCK_THRESHOLD.as_nanos() as u64),
+            #[cfg(all(feature = "debug-deadlock", test))]
+            deadlock_reports: AtomicUsize::default(),
+            #[cfg(feature = "fairness-trace")]
+            grants: FairnessTrace::default(),
+            #[cfg(feature = "throughput")]
+            throughput: Throughput::new(Instant::now()),
+            #[cfg(test)]
+            notifications: AtomicUsize::default(),
+            #[cfg(test)]
+            long_holds: AtomicUsize::default(),
+            #[cfg(test)]
+            before_register: Mutex::new(None)
         }
     }
 
     #[inline]
-    pub fn try_acquire(&self) -> bool {
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.try_acquire_n(1).is_some()
+    }
+
+    #[inline]
+    #[track_caller]
+    /// Attempt to acquire `n` accesses at once, returning the number of active accesses
+    /// right after the acquisition on success.
+    ///
+    /// Either all `n` accesses are acquired, or none at all. Accounting errors are
+    /// reported at the location of the caller.
+    pub(crate) fn try_acquire_n(&self, n: usize) -> Option<usize> {
+        let mut contended = 0;
         loop {
             let current_active = self.active.load(Ordering::SeqCst);
-            assert!(current_active <= self.capacity);
-            if current_active == self.capacity {
-                return false;
+            // The capacity is read afresh on every attempt, so that a concurrent shrink
+            // is respected. It may have dropped below the number of active accesses.
+            let capacity = self.capacity();
+            if capacity.saturating_sub(current_active) < n {
+                return None;
             }
-            let previous_active = self.active.compare_and_swap(
+            let result = self.active.compare_exchange(
                 current_active,
-                current_active + 1,
+                current_active + n,
+                Ordering::SeqCst,
                 Ordering::SeqCst
             );
-            if previous_active == current_active {
-                return true;
+            if result.is_ok() {
+                debug_assert!(current_active + n <= capacity,
+                    "active count exceeds capacity, acquired at {}", Location::caller());
+                #[cfg(feature = "stats")]
+                self.peak_active.fetch_max(current_active + n, Ordering::Relaxed);
+                self.acquired(current_active + n);
+                return Some(current_active + n);
+            }
+            // Another thread modified the count concurrently, back off before retrying.
+            contended += 1;
+            if contended < YIELD_THRESHOLD {
+                hint::spin_loop();
+            } else {
+                contended = 0;
+                thread::yield_now();
+            }
+        }
+    }
+
+    /// Raise the number of active accesses from exactly `from` to `to`,
+    /// failing if any other access was acquired or released in the meantime.
+    pub(crate) fn try_upgrade(&self, from: usize, to: usize) -> bool {
+        debug_assert!(from <= to && to <= self.capacity());
+        let result = self.active.compare_exchange(from, to, Ordering::SeqCst, Ordering::SeqCst);
+        #[cfg(feature = "stats")]
+        {
+            if result.is_ok() {
+                self.peak_active.fetch_max(to, Ordering::Relaxed);
+            }
+        }
+        result.is_ok()
+    }
+
+    #[inline]
+    /// Draw the next number from the sequence of acquisitions.
+    pub(crate) fn next_sequence(&self) -> u64 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::SeqCst)
+    }
+
+    /// Grow the capacity by `k`, handing the new accesses to blocked acquirers.
+    pub(crate) fn add_permits(&self, k: usize) {
+        let mut pending = self.resize.lock();
+        if self.projected.load(Ordering::SeqCst) {
+            // More capacity would let other guards reach the exclusively borrowed resource,
+            // so the growth is applied once the projection ends.
+            *pending = pending.saturating_add(k);
+            return;
+        }
+        let previous_capacity = self.grow(k);
+        drop(pending);
+        self.on_grown(previous_capacity);
+    }
+
+    /// Grow the capacity by `k`, saturating at `usize::MAX`, and return the previous capacity.
+    fn grow(&self, k: usize) -> usize {
+        let result = self.capacity.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |capacity| {
+            Some(capacity.saturating_add(k))
+        });
+        result.unwrap_or_else(|capacity| capacity)
+    }
+
+    /// Grow the capacity by one to acquire a single access, as long as it stays within `max`.
+    ///
+    /// The borrowed capacity is to be given back through `remove_permits` once the access
+    /// is released. Returns the number of active accesses right after the acquisition.
+    pub(crate) fn try_acquire_borrowed(&self, max: usize) -> Option<usize> {
+        let pending = self.resize.lock();
+        if self.projected.load(Ordering::SeqCst) || self.capacity() >= max {
+            return None;
+        }
+        self.capacity.fetch_add(1, Ordering::SeqCst);
+        drop(pending);
+        let active = self.try_acquire_n(1);
+        if active.is_none() {
+            // A concurrent acquirer took the borrowed capacity first.
+            self.remove_permits(1);
+        }
+        active
+    }
+
+    /// Replace the capacity, handing any growth to blocked acquirers like `add_permits`.
+    ///
+    /// Marks the semaphore as gated, so that acquirers block on a capacity of zero
+    /// instead of giving up right away.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.gated.store(true, Ordering::SeqCst);
+        let mut pending = self.resize.lock();
+        let current = self.capacity();
+        if capacity > current && self.projected.load(Ordering::SeqCst) {
+            *pending = capacity - current;
+            return;
+        }
+        *pending = 0;
+        let previous_capacity = self.capacity.swap(capacity, Ordering::SeqCst);
+        drop(pending);
+        if capacity > previous_capacity {
+            self.on_grown(previous_capacity);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_gated(&self) -> bool {
+        self.gated.load(Ordering::SeqCst)
+    }
+
+    /// Read the state of the semaphore, with the capacity and active count taken at the same time.
+    pub(crate) fn snapshot(&self) -> SemaphoreSnapshot {
+        // The capacity changes rarely, so it is read again until it was stable around the
+        // active count, which makes both describe the same point in time.
+        let (capacity, active) = loop {
+            let capacity = self.capacity();
+            let active = self.active();
+            if self.capacity() == capacity {
+                break (capacity, active);
+            }
+        };
+        SemaphoreSnapshot {
+            capacity,
+            active,
+            available: capacity.saturating_sub(active),
+            waiters: self.waiters.load(Ordering::SeqCst) + self.queue.len() + self.slot_waiters(),
+            is_shutdown: self.is_closed()
+        }
+    }
+
+    pub(crate) fn enter_priority(&self, priority: u32) {
+        *self.priorities.lock().entry(priority).or_insert(0) += 1;
+    }
+
+    pub(crate) fn leave_priority(&self, priority: u32) {
+        let mut priorities = self.priorities.lock();
+        let remove = priorities.get_mut(&priority).is_some_and(|count| {
+            *count -= 1;
+            *count == 0
+        });
+        if remove {
+            priorities.remove(&priority);
+        }
+    }
+
+    /// Whether any acquirer is waiting with a priority above the given one.
+    pub(crate) fn has_waiter_above(&self, priority: u32) -> bool {
+        self.priorities.lock().keys().next_back().is_some_and(|&highest| highest > priority)
+    }
+
+    /// Shrink the capacity by up to `k`, returning by how much it was shrunk.
+    pub(crate) fn take_capacity(&self, k: usize) -> usize {
+        let result = self.capacity.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |capacity| {
+            Some(capacity.saturating_sub(k))
+        });
+        let previous_capacity = result.unwrap_or_else(|capacity| capacity);
+        if previous_capacity > 0 && k > 0 && !self.is_gated() {
+            self.on_shrunk();
+        }
+        previous_capacity.min(k)
+    }
+
+    /// Wake up all blocked acquirers after the capacity has been shrunk, so that those
+    /// asking for more accesses than are left can observe that they will never be granted.
+    fn on_shrunk(&self) {
+        let lock = self.lock.lock();
+        self.cond.notify_all();
+        drop(lock);
+        self.queue.wake_all();
+        #[cfg(feature = "async")]
+        self.slot.wake();
+        self.wake_all();
+    }
+
+    /// Whether `n` accesses can never be granted, because they exceed the capacity
+    /// of a semaphore that is not gated.
+    #[inline]
+    pub(crate) fn exceeds_capacity(&self, n: usize) -> bool {
+        n > self.capacity() && !self.is_gated()
+    }
+
+    /// Shrink the capacity by `k`, down to at most zero.
+    ///
+    /// Accesses that are already held stay valid, even if the active count now exceeds the
+    /// capacity. No new access is granted until enough of them have been released.
+    pub(crate) fn remove_permits(&self, k: usize) {
+        self.take_capacity(k);
+    }
+
+    fn on_grown(&self, previous_capacity: usize) {
+        // Like a release, only as many queued acquirers are woken as the new accesses can satisfy.
+        if !self.queue.is_empty() {
+            self.queue.wake(self.available());
+        }
+        let made_available = self.active() >= previous_capacity;
+        #[cfg(feature = "async")]
+        {
+            if made_available {
+                self.slot.wake();
             }
         }
+        let made_available = made_available && self.waiters.load(Ordering::SeqCst) > 0;
+        // Those waiting for more than one access to be available may need any growth.
+        let headroom_grown = self.headroom_waiters.load(Ordering::SeqCst) > 0;
+        if made_available || headroom_grown {
+            #[cfg(test)]
+            self.notifications.fetch_add(1, Ordering::SeqCst);
+            let guard = self.lock.lock();
+            self.cond.notify_all();
+            drop(guard);
+            self.wake_all();
+        }
+    }
+
+    /// Report an acquisition, leaving `active` accesses active.
+    fn acquired(&self, active: usize) {
+        #[cfg(feature = "throughput")]
+        self.throughput.record_acquire(Instant::now());
+        if let Some(ref observer) = self.observer {
+            observer.on_acquire(active);
+        }
+    }
+
+    /// Report a release, leaving `active` accesses active.
+    fn released(&self, active: usize) {
+        #[cfg(feature = "throughput")]
+        self.throughput.record_release(Instant::now());
+        if let Some(ref observer) = self.observer {
+            observer.on_release(active);
+        }
     }
 
     #[inline]
-    pub fn release(&self) {
-        let previous_active = self.active.fetch_sub(1, Ordering::SeqCst);
-        if previous_active == 1 {
+    #[track_caller]
+    pub(crate) fn release(&self) {
+        self.release_n(1)
+    }
+
+    #[inline]
+    #[track_caller]
+    /// Release `n` accesses, reporting accounting errors at the location of the caller.
+    pub(crate) fn release_n(&self, n: usize) {
+        let n = n - self.hand_off(n);
+        if n == 0 {
+            return;
+        }
+        let previous_active = self.active.fetch_sub(n, Ordering::SeqCst);
+        debug_assert!(previous_active >= n,
+            "released more accesses than were acquired, at {}", Location::caller());
+        self.released(previous_active - n);
+        // Queued acquirers are woken individually, only as many as the released accesses can satisfy.
+        if !self.queue.is_empty() {
+            self.queue.wake(self.available());
+        }
+        // Those waiting on the condition variable only ever wait while the semaphore is at
+        // capacity, so they only need to be notified when this release makes capacity available.
+        // Those waiting for the semaphore to become inactive need to be notified
+        // when the last access is released.
+        let capacity = self.capacity();
+        let made_available = previous_active >= capacity && previous_active - n < capacity;
+        // The registration in the waker slot is not counted as a waiter, so that
+        // waking it alone does not need to take any lock.
+        #[cfg(feature = "async")]
+        {
+            if made_available {
+                self.slot.wake();
+            }
+        }
+        let made_available = made_available && self.waiters.load(Ordering::SeqCst) > 0;
+        let became_inactive = previous_active == n;
+        // Those waiting for more than one access to be available may need any release.
+        let headroom_grown = self.headroom_waiters.load(Ordering::SeqCst) > 0;
+        if made_available || became_inactive || headroom_grown {
+            #[cfg(test)]
+            self.notifications.fetch_add(1, Ordering::SeqCst);
             let guard = self.lock.lock();
             self.cond.notify_all();
-            drop(guard)
+            drop(guard);
+            self.wake_all();
+        }
+        if became_inactive && self.is_closed() {
+            // This was the last access to a semaphore that has been shut down.
+            self.complete_shutdown();
+        }
+    }
+
+    /// Hand up to `n` released accesses directly to queued fair acquirers,
+    /// returning how many were handed over.
+    ///
+    /// The accesses never become available in between, so that a non-fair acquirer
+    /// can not barge ahead of a fair acquirer that is already queued.
+    fn hand_off(&self, n: usize) -> usize {
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return 0;
+        }
+        let mut queue = self.lock.lock();
+        // After a shrink, only as many accesses are handed over as fit within the capacity.
+        let room = self.capacity().saturating_sub(self.active().saturating_sub(n));
+        let handed = n.min(queue.tickets.len() - queue.handed).min(room);
+        if handed > 0 {
+            queue.handed += handed;
+            self.cond.notify_all();
+            self.released(self.active() - handed);
+        }
+        handed
+    }
+
+    /// Run the given callback once the semaphore has been shut down
+    /// and all access has been released.
+    ///
+    /// Runs the callback right away if there is no active access anymore,
+    /// otherwise it runs on the thread that releases the last access.
+    pub(crate) fn on_shutdown_complete(&self, callback: Box<dyn FnOnce() + Send>) {
+        let mut completion = self.completion.lock();
+        if self.is_active() {
+            *completion = Some(callback);
+        } else {
+            drop(completion);
+            callback();
+        }
+    }
+
+    fn complete_shutdown(&self) {
+        let callback = self.completion.lock().take();
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+
+    /// Mark the resource as mutably borrowed through a guard.
+    ///
+    /// Pairs with `is_projected`: a reference count read after this, and a reference
+    /// taken before checking `is_projected`, can not both miss each other.
+    ///
+    /// While the projection is held, the capacity does not grow.
+    pub(crate) fn begin_projection(&self) {
+        let _pending = self.resize.lock();
+        self.projected.store(true, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn end_projection(&self) {
+        let mut pending = self.resize.lock();
+        self.projected.store(false, Ordering::SeqCst);
+        let k = mem::replace(&mut *pending, 0);
+        if k > 0 {
+            let previous_capacity = self.grow(k);
+            drop(pending);
+            self.on_grown(previous_capacity);
+        }
+    }
+
+    /// To be called after taking a reference to the resource outside of a guard.
+    pub(crate) fn is_projected(&self) -> bool {
+        atomic::fence(Ordering::SeqCst);
+        self.projected.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn enable_poisoning(&self) {
+        self.poisoning.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(crate) fn is_poisoning(&self) -> bool {
+        self.poisoning.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn poison(&self) {
+        self.poisoned.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Stop granting new access, and wake up all blocked acquirers so they can observe it.
+    pub(crate) fn close(&self) {
+        let lock = self.lock.lock();
+        let was_closed = self.closed.swap(true, Ordering::SeqCst);
+        self.cond.notify_all();
+        drop(lock);
+        if let (false, Some(observer)) = (was_closed, self.observer.as_ref()) {
+            observer.on_shutdown();
+        }
+        self.queue.wake_all();
+        #[cfg(feature = "async")]
+        self.slot.wake();
+        self.wake_all();
+    }
+
+    pub(crate) fn set_hold_threshold(&mut self, threshold: Duration) {
+        self.hold_threshold = Some(threshold);
+    }
+
+    #[inline]
+    pub(crate) fn hold_threshold(&self) -> Option<Duration> {
+        self.hold_threshold
+    }
+
+    /// Report the release of a guard that was held for longer than the hold threshold.
+    pub(crate) fn check_hold(&self, held: Duration, site: Option<&'static Location<'static>>) {
+        let threshold = match self.hold_threshold {
+            Some(threshold) if held > threshold => threshold,
+            _ => return
+        };
+        #[cfg(test)]
+        self.long_holds.fetch_add(1, Ordering::SeqCst);
+        Self::report_long_hold(held, threshold, site);
+    }
+
+    #[cfg(feature = "hold-warnings")]
+    fn report_long_hold(held: Duration, threshold: Duration, site: Option<&'static Location<'static>>) {
+        match site {
+            Some(site) => eprintln!(
+                "semaphore: guard acquired at {} was held for {:?}, longer than {:?}",
+                site, held, threshold
+            ),
+            None => eprintln!("semaphore: guard was held for {:?}, longer than {:?}", held, threshold)
+        }
+    }
+
+    #[cfg(not(feature = "hold-warnings"))]
+    #[inline]
+    fn report_long_hold(_held: Duration, _threshold: Duration, _site: Option<&'static Location<'static>>) {}
+
+    /// Report a failed attempt to acquire access without blocking, passing the reason through.
+    #[inline]
+    pub(crate) fn deny(&self, reason: TryAccessError) -> TryAccessError {
+        if let Some(ref observer) = self.observer {
+            observer.on_denied(reason);
+        }
+        reason
+    }
+
+    pub(crate) fn set_retained_waiters(&self, retained: usize) {
+        self.queue.set_retained(retained);
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn peak_active(&self) -> usize {
+        self.peak_active.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn reset_peak(&self) {
+        self.peak_active.store(self.active(), Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "debug-holders")]
+    pub(crate) fn holders(&self) -> &Holders {
+        &self.holders
+    }
+
+    /// Mark the semaphore as having no handles left, only guards.
+    #[cfg(feature = "leak-detection")]
+    pub(crate) fn orphan(&self) {
+        self.orphaned.store(true, Ordering::SeqCst);
+    }
+
+    /// Panic if all handles of the semaphore are gone while a guard is released.
+    ///
+    /// This usually means a guard was stashed somewhere it outlives the scope
+    /// the semaphore was meant to be used in.
+    #[cfg(feature = "leak-detection")]
+    pub(crate) fn check_orphaned(&self) {
+        if self.orphaned.load(Ordering::SeqCst) && !thread::panicking() {
+            panic!("semaphore guard released after all handles to its semaphore were dropped");
         }
     }
 
     #[inline]
-    pub fn is_active(&self) -> bool {
+    pub(crate) fn is_active(&self) -> bool {
         self.active.load(Ordering::SeqCst) > 0
     }
 
     #[inline]
-    pub fn wait_until_inactive(&self) {
+    fn available(&self) -> usize {
+        self.capacity().saturating_sub(self.active.load(Ordering::SeqCst))
+    }
+
+    #[inline]
+    pub(crate) fn wait_until_inactive(&self) {
         let mut lock = self.lock.lock();
 
         while self.is_active() {
             self.cond.wait(&mut lock);
         }
     }
+
+    /// Block until no access is held anymore, reporting the number of accesses
+    /// still held at every `interval` in between.
+    pub(crate) fn wait_until_inactive_with<F: FnMut(usize)>(&self, interval: Duration, mut f: F) {
+        let mut lock = self.lock.lock();
+        let mut next = Instant::now() + interval;
+
+        while self.is_active() {
+            // Other notifications on the condition variable do not delay the next report.
+            self.cond.wait_until(&mut lock, next);
+            let now = Instant::now();
+            if now < next {
+                continue;
+            }
+            next = now + interval;
+            let active = self.active();
+            if active > 0 {
+                MutexGuard::unlocked(&mut lock, || f(active));
+            }
+        }
+    }
+
+    /// Acquire in first-in-first-out order with respect to other fair acquirers,
+    /// blocking until it is this acquirer's turn and capacity is available.
+    ///
+    /// Returns `false` if the deadline passed or the semaphore was closed first,
+    /// in which case the acquirer has left the queue again. Capacity that is available at the deadline is
+    /// still taken, so the acquirer never gives up on a permit it could have had.
+    ///
+    /// Accesses released while fair acquirers are queued are handed to them through `hand_off`.
+    pub(crate) fn acquire_fair(&self, deadline: Option<Instant>) -> bool {
+        let ticket = self.enqueue_fair();
+        self.wait_fair(ticket, deadline)
+    }
+
+    /// Join the queue of fair acquirers, returning the ticket to wait for with `wait_fair`.
+    ///
+    /// The ticket holds up the fair acquirers behind it until it is either waited for,
+    /// or given up through `leave_fair`.
+    pub(crate) fn enqueue_fair(&self) -> usize {
+        let mut queue = self.lock.lock();
+        let ticket = queue.next_ticket;
+        queue.next_ticket = queue.next_ticket.wrapping_add(1);
+        queue.tickets.push_back(ticket);
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        ticket
+    }
+
+    /// Number of fair acquirers ahead of the given ticket in the queue.
+    pub(crate) fn fair_position(&self, ticket: usize) -> usize {
+        let queue = self.lock.lock();
+        queue.tickets.iter().position(|&queued| queued == ticket).unwrap_or(0)
+    }
+
+    /// Give up a ticket without waiting for it, passing on any access handed to it.
+    pub(crate) fn leave_fair(&self, ticket: usize) {
+        let mut queue = self.lock.lock();
+        let position = queue.tickets.iter().position(|&queued| queued == ticket).unwrap();
+        let handed = position < queue.handed;
+        if handed {
+            queue.handed -= 1;
+        }
+        queue.remove(position, self.queue.retained());
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        self.cond.notify_all();
+        drop(queue);
+        if handed {
+            self.release();
+        }
+    }
+
+    /// Wait for the turn of a ticket returned from `enqueue_fair`, like `acquire_fair`.
+    pub(crate) fn wait_fair(&self, ticket: usize, deadline: Option<Instant>) -> bool {
+        let mut queue = self.lock.lock();
+        let mut timed_out = false;
+        let acquired = loop {
+            let position = queue.tickets.iter().position(|&queued| queued == ticket).unwrap();
+            if position < queue.handed {
+                // A release handed its access to this acquirer, keeping it active throughout.
+                queue.handed -= 1;
+                queue.remove(position, self.queue.retained());
+                self.acquired(self.active());
+                break true;
+            }
+            if position == queue.handed && self.try_acquire() {
+                queue.remove(position, self.queue.retained());
+                break true;
+            }
+            if timed_out || self.is_closed() {
+                queue.remove(position, self.queue.retained());
+                break false;
+            }
+            match deadline {
+                Some(deadline) => {
+                    timed_out = self.cond.wait_until(&mut queue, deadline).timed_out();
+                },
+                None => {
+                    self.cond.wait(&mut queue);
+                }
+            }
+        };
+
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        // The head of the queue has changed, so the next fair acquirer needs to re-evaluate.
+        self.cond.notify_all();
+        acquired
+    }
+
+    /// Acquire `n` accesses at once, blocking until they are available or the deadline passes.
+    ///
+    /// Blocked acquirers are queued in arrival order, and every release only wakes
+    /// as many of them as it can satisfy. An acquirer that is woken but loses the race
+    /// against a non-blocking acquisition re-enters the queue at the front.
+    ///
+    /// If a token is given, the acquirer gives up as soon as it is cancelled.
+    ///
+    /// Returns `false` if the token was cancelled, the deadline passed, the semaphore
+    /// was closed first, or `n` exceeds the capacity of a semaphore that is not gated.
+    pub(crate) fn acquire_blocking(&self, n: usize, deadline: Option<Instant>, token: Option<&dyn Cancellable>) -> bool {
+        let cancelled = || token.is_some_and(|token| token.is_cancelled());
+        #[cfg(feature = "stats")]
+        let mut blocked_since = None;
+        let mut front = false;
+        loop {
+            if cancelled() {
+                // Capacity handed to this acquirer when it was woken is passed on.
+                if front && !self.queue.is_empty() {
+                    self.queue.wake(self.available());
+                }
+                return false;
+            }
+            if self.try_acquire_n(n).is_some() {
+                #[cfg(feature = "stats")]
+                self.record_wait(blocked_since);
+                return true;
+            }
+            if self.is_closed() || self.exceeds_capacity(n) {
+                return false;
+            }
+            let parker = Arc::new(Parker::default());
+            let waker = token.map(|token| {
+                let waker = Waker::from(parker.clone());
+                token.subscribe(&waker);
+                (token, waker)
+            });
+            self.queue.push(n, &parker, front);
+            // Check again now that the parker is queued, so that a release
+            // racing with the registration is not missed.
+            if self.try_acquire_n(n).is_some() {
+                self.queue.remove(&parker);
+                Self::unsubscribe(waker);
+                #[cfg(feature = "stats")]
+                self.record_wait(blocked_since);
+                return true;
+            }
+            if self.is_closed() || self.exceeds_capacity(n) || cancelled() {
+                self.queue.remove(&parker);
+                Self::unsubscribe(waker);
+                return false;
+            }
+            #[cfg(feature = "stats")]
+            {
+                blocked_since.get_or_insert_with(Instant::now);
+            }
+            let notified = self.park(&parker, n, deadline);
+            Self::unsubscribe(waker);
+            // A parker that is no longer queued has been woken, even if it timed out concurrently.
+            if !notified && self.queue.remove(&parker) {
+                let expired = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                if !expired && !cancelled() {
+                    // Interrupted by the token without it being cancelled.
+                    front = true;
+                    continue;
+                }
+                if !cancelled() && self.try_acquire_n(n).is_some() {
+                    #[cfg(feature = "stats")]
+                    self.record_wait(blocked_since);
+                    return true;
+                }
+                // This acquirer may have been holding up the ones behind it,
+                // which could be satisfied by the accesses available now.
+                if !self.queue.is_empty() {
+                    self.queue.wake(self.available());
+                }
+                return false;
+            }
+            front = true;
+        }
+    }
+
+    #[cfg(not(all(feature = "debug-deadlock", debug_assertions)))]
+    #[inline]
+    fn park(&self, parker: &Parker, _n: usize, deadline: Option<Instant>) -> bool {
+        parker.park(deadline)
+    }
+
+    /// Park like `Parker::park`, reporting a probable deadlock if the wait exceeds the threshold.
+    #[cfg(all(feature = "debug-deadlock", debug_assertions))]
+    fn park(&self, parker: &Parker, n: usize, deadline: Option<Instant>) -> bool {
+        let threshold = self.deadlock_threshold();
+        let reported_at = Instant::now() + threshold;
+        if deadline.is_some_and(|deadline| deadline <= reported_at) {
+            return parker.park(deadline);
+        }
+        if parker.park(Some(reported_at)) {
+            return true;
+        }
+        if !parker.is_interrupted() {
+            self.report_deadlock(n, threshold);
+        }
+        parker.park(deadline)
+    }
+
+    #[cfg(all(feature = "debug-deadlock", debug_assertions))]
+    fn report_deadlock(&self, n: usize, threshold: Duration) {
+        #[cfg(test)]
+        self.deadlock_reports.fetch_add(1, Ordering::SeqCst);
+        eprintln!(
+            "semaphore: acquirer of {} accesses blocked for more than {:?} while {} accesses are held, \
+             probably due to a deadlock or a leaked guard",
+            n, threshold, self.active()
+        );
+        #[cfg(feature = "debug-holders")]
+        {
+            for site in self.holders.sites() {
+                eprintln!("semaphore: access held since {}", site);
+            }
+        }
+    }
+
+    #[cfg(feature = "debug-deadlock")]
+    pub(crate) fn set_deadlock_threshold(&self, threshold: Duration) {
+        let nanos = threshold.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.deadlock_threshold.store(nanos, Ordering::Relaxed);
+    }
+
+    #[cfg(all(feature = "debug-deadlock", debug_assertions))]
+    fn deadlock_threshold(&self) -> Duration {
+        Duration::from_nanos(self.deadlock_threshold.load(Ordering::Relaxed))
+    }
+
+    fn unsubscribe(waker: Option<(&dyn Cancellable, Waker)>) {
+        if let Some((token, waker)) = waker {
+            token.unsubscribe(&waker);
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    fn record_wait(&self, blocked_since: Option<Instant>) {
+        if let Some(blocked_since) = blocked_since {
+            self.waits.record(blocked_since.elapsed());
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn wait_stats(&self) -> WaitStats {
+        self.waits.snapshot()
+    }
+
+    #[cfg(feature = "throughput")]
+    pub(crate) fn recent_throughput(&self) -> (f64, f64) {
+        self.throughput.rates(Instant::now())
+    }
+
+    #[cfg(feature = "fairness-trace")]
+    pub(crate) fn trace_grant(&self, acquirer: usize) {
+        self.grants.record(acquirer)
+    }
+
+    #[cfg(feature = "fairness-trace")]
+    pub(crate) fn fairness_trace(&self) -> Vec<usize> {
+        self.grants.snapshot()
+    }
+
+    /// Block until the semaphore has capacity available.
+    ///
+    /// Returns immediately if the semaphore is not at capacity, or has been closed.
+    /// Capacity is not reserved, so a subsequent acquisition attempt may still fail.
+    pub fn wait(&self) {
+        self.wait_for_capacity(None);
+    }
+
+    /// Block until the semaphore has capacity available, or the timeout expires.
+    ///
+    /// Returns `false` if the timeout expired while the semaphore was still at capacity.
+    /// Returns `true` early if the semaphore has been closed, so that acquirers can observe it.
+    /// Capacity is not reserved, so a subsequent acquisition attempt may still fail.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_for_capacity(Some(Instant::now() + timeout))
+    }
+
+    fn wait_for_capacity(&self, deadline: Option<Instant>) -> bool {
+        self.wait_for_available(1, deadline)
+    }
+
+    /// Block until at least `k` accesses are available at the same time, without acquiring them.
+    ///
+    /// Returns `false` if the deadline passed first.
+    pub(crate) fn wait_for_available(&self, k: usize, deadline: Option<Instant>) -> bool {
+        let mut lock = self.lock.lock();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        if k > 1 {
+            self.headroom_waiters.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let mut timed_out = false;
+        while self.available() < k && !self.is_closed() && !timed_out {
+            match deadline {
+                Some(deadline) => {
+                    timed_out = self.cond.wait_until(&mut lock, deadline).timed_out();
+                },
+                None => {
+                    self.cond.wait(&mut lock);
+                }
+            }
+        }
+
+        if k > 1 {
+            self.headroom_waiters.fetch_sub(1, Ordering::SeqCst);
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        self.available() >= k || self.is_closed()
+    }
+
+    #[cfg(feature = "async")]
+    /// Register a waker to be woken by the next release.
+    ///
+    /// Passing the key of a previous registration that has not been woken yet
+    /// replaces its waker instead of adding another entry. Returns the key
+    /// of the registration, which can be used to deregister it.
+    pub(crate) fn register_waker(&self, key: Option<usize>, waker: &Waker) -> usize {
+        #[cfg(test)]
+        self.run_before_register();
+        // The first registration takes the slot, which it keeps until it is deregistered,
+        // so that a lone waiter is registered and woken without contending on the lock.
+        let key = match key {
+            Some(key) if self.slot_key.load(Ordering::SeqCst) == key => {
+                self.slot.register(waker);
+                return key;
+            },
+            Some(key) => key,
+            None => {
+                let key = self.next_key.fetch_add(1, Ordering::Relaxed) % FREE_SLOT;
+                let free = self.slot_key.load(Ordering::Relaxed) == FREE_SLOT;
+                if free && self.slot_key.compare_exchange(FREE_SLOT, key, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                    self.slot.register(waker);
+                    return key;
+                }
+                key
+            }
+        };
+        let mut wakers = self.wakers.lock();
+        if let Some(entry) = wakers.entries.iter_mut().find(|entry| entry.0 == key) {
+            if !entry.1.will_wake(waker) {
+                entry.1 = waker.clone();
+            }
+            return key;
+        }
+        wakers.entries.push((key, waker.clone()));
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        key
+    }
+
+    /// Register a waker to be woken by the next release, without a key.
+    ///
+    /// Registering a waker that would wake the same task as one that is already
+    /// registered this way has no effect, so that repeated polls don't pile up entries.
+    pub(crate) fn register_task(&self, waker: &Waker) {
+        #[cfg(test)]
+        self.run_before_register();
+        let mut wakers = self.wakers.lock();
+        if !wakers.tasks.iter().any(|task| task.will_wake(waker)) {
+            wakers.tasks.push(waker.clone());
+            self.waiters.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    /// Remove a registration that has not been woken yet.
+    pub(crate) fn deregister_waker(&self, key: usize) {
+        if self.slot_key.load(Ordering::SeqCst) == key {
+            drop(self.slot.take());
+            self.slot_key.store(FREE_SLOT, Ordering::SeqCst);
+            return;
+        }
+        let mut wakers = self.wakers.lock();
+        if let Some(index) = wakers.entries.iter().position(|entry| entry.0 == key) {
+            wakers.entries.swap_remove(index);
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+            let capacity = wakers.entries.capacity();
+            if let Some(target) = reclaim_target(wakers.entries.len(), capacity, self.queue.retained()) {
+                wakers.entries.shrink_to(target);
+            }
+        }
+    }
+
+    #[inline]
+    fn slot_waiters(&self) -> usize {
+        #[cfg(feature = "async")]
+        {
+            (self.slot_key.load(Ordering::SeqCst) != FREE_SLOT) as usize
+        }
+        #[cfg(not(feature = "async"))]
+        {
+            0
+        }
+    }
+
+    fn wake_all(&self) {
+        let (entries, tasks) = {
+            let mut wakers = self.wakers.lock();
+            self.waiters.fetch_sub(wakers.entries.len() + wakers.tasks.len(), Ordering::SeqCst);
+            (mem::take(&mut wakers.entries), mem::take(&mut wakers.tasks))
+        };
+        for (_, waker) in entries {
+            waker.wake();
+        }
+        for waker in tasks {
+            waker.wake();
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn waiter_count(&self) -> usize {
+        self.waiters.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn queued_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn queue_storage_capacity(&self) -> usize {
+        self.queue.storage_capacity()
+    }
+
+    #[cfg(all(feature = "debug-deadlock", test))]
+    pub(crate) fn deadlock_report_count(&self) -> usize {
+        self.deadlock_reports.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn long_hold_count(&self) -> usize {
+        self.long_holds.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn notification_count(&self) -> usize {
+        self.notifications.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_before_register<F: FnOnce() + Send + 'static>(&self, hook: F) {
+        *self.before_register.lock() = Some(Box::new(hook));
+    }
+
+    #[cfg(test)]
+    fn run_before_register(&self) {
+        let hook = self.before_register.lock().take();
+        if let Some(hook) = hook {
+            hook();
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn registered_wakers(&self) -> usize {
+        let wakers = self.wakers.lock();
+        #[cfg(feature = "async")]
+        let slot = self.slot.is_registered() as usize;
+        #[cfg(not(feature = "async"))]
+        let slot = 0;
+        wakers.entries.len() + wakers.tasks.len() + slot
+    }
 }
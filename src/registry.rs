@@ -0,0 +1,51 @@
+use parking_lot::Mutex;
+
+use snapshot::SemaphoreSnapshot;
+use super::{Semaphore, WeakSemaphore};
+
+type Snapshotter = Box<dyn Fn() -> Option<SemaphoreSnapshot> + Send + Sync>;
+
+/// Collection of named semaphores, to export all of their snapshots at once.
+///
+/// The registry only holds weak handles, so it does not keep registered semaphores alive.
+/// Semaphores that have been dropped are pruned the next time snapshots are exported.
+#[derive(Default)]
+pub struct SemaphoreRegistry {
+    entries: Mutex<Vec<(String, Snapshotter)>>
+}
+
+impl SemaphoreRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        SemaphoreRegistry::default()
+    }
+
+    /// Add a semaphore to the registry under the given name.
+    ///
+    /// Names are not required to be unique, and a semaphore can be registered more than once.
+    pub fn register<T, S>(&self, name: S, semaphore: &Semaphore<T>)
+        where T: ?Sized + Send + Sync + 'static, S: Into<String>
+    {
+        let weak: WeakSemaphore<T> = semaphore.downgrade();
+        let snapshot = move || weak.upgrade().map(|semaphore| semaphore.snapshot());
+        self.entries.lock().push((name.into(), Box::new(snapshot)));
+    }
+
+    /// Take a snapshot of every registered semaphore that is still alive, in registration order.
+    ///
+    /// Semaphores that have been dropped since they were registered are removed from the registry.
+    pub fn snapshot_all(&self) -> Vec<(String, SemaphoreSnapshot)> {
+        let mut entries = self.entries.lock();
+        let mut snapshots = Vec::with_capacity(entries.len());
+        entries.retain(|(name, snapshot)| {
+            match snapshot() {
+                Some(snapshot) => {
+                    snapshots.push((name.clone(), snapshot));
+                    true
+                },
+                None => false
+            }
+        });
+        snapshots
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use guard;
+use raw::RawSemaphore;
+use super::SemaphoreGuard;
+
+/// Access to a semaphore that has been acquired, but not yet turned into a guard.
+///
+/// Returned from `Semaphore::reserve`. The reserved access counts against the capacity
+/// right away, and is either turned into a guard through `commit`, or given back through
+/// `cancel`. Dropping a reservation cancels it.
+pub struct Reservation<T: ?Sized> {
+    raw: Arc<RawSemaphore>,
+    resource: Option<Arc<T>>
+}
+
+pub fn new<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: &Arc<T>) -> Reservation<T> {
+    Reservation {
+        raw: raw.clone(),
+        resource: Some(resource.clone())
+    }
+}
+
+impl<T: ?Sized> Reservation<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Turn the reservation into a guard holding the reserved access.
+    ///
+    /// This can not fail, even if the semaphore has been shut down in the meantime,
+    /// since a shutdown waits for reserved access to be released like for any guard.
+    pub fn commit(mut self) -> SemaphoreGuard<T> {
+        let resource = self.resource.take().unwrap();
+        guard::new(&self.raw, &resource)
+    }
+
+    /// Give the reserved access back to the semaphore.
+    pub fn cancel(self) {}
+}
+
+impl<T: ?Sized> Drop for Reservation<T> {
+    fn drop(&mut self) {
+        // Once committed, the access is released by the guard instead.
+        if self.resource.take().is_some() {
+            self.raw.release();
+            #[cfg(feature = "leak-detection")]
+            self.raw.check_orphaned();
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::ops::{Deref, DerefMut};
+
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use raw::RawSemaphore;
+use super::TryAccessError;
+
+/// Counting semaphore that distinguishes shared readers from exclusive writers.
+///
+/// Grants up to `capacity` read guards at the same time, or a single write guard.
+/// A writer takes all of the capacity at once, so it can only be granted while no
+/// reader is active, and no reader can be granted while it is held.
+///
+/// Unlike `Semaphore`, guards borrow the semaphore, and it can not be shut down.
+pub struct RwSemaphore<T> {
+    raw: RawSemaphore,
+    resource: RwLock<T>
+}
+
+impl<T> RwSemaphore<T> {
+    /// Create a new read/write semaphore around a resource,
+    /// allowing up to `capacity` concurrent readers.
+    ///
+    /// The capacity is at least one, in which case readers are exclusive as well.
+    pub fn new(capacity: usize, resource: T) -> Self {
+        RwSemaphore {
+            raw: RawSemaphore::new(capacity.max(1)),
+            resource: RwLock::new(resource)
+        }
+    }
+
+    /// Attempt to acquire shared read access to the underlying resource.
+    ///
+    /// If all capacity is taken by readers, or a writer is active,
+    /// `TryAccessError::NoCapacity` will be returned.
+    pub fn try_read(&self) -> Result<RwReadGuard<'_, T>, TryAccessError> {
+        if self.raw.try_acquire_n(1).is_none() {
+            return Err(TryAccessError::NoCapacity);
+        }
+        Ok(RwReadGuard {
+            semaphore: self,
+            guard: Some(self.resource.read())
+        })
+    }
+
+    /// Attempt to acquire exclusive write access to the underlying resource.
+    ///
+    /// If any reader or writer is active, `TryAccessError::NoCapacity` will be returned.
+    pub fn try_write(&self) -> Result<RwWriteGuard<'_, T>, TryAccessError> {
+        if self.raw.try_acquire_n(self.raw.capacity()).is_none() {
+            return Err(TryAccessError::NoCapacity);
+        }
+        Ok(self.write_guard())
+    }
+
+    fn write_guard(&self) -> RwWriteGuard<'_, T> {
+        // Holding all of the capacity excludes every other guard, and guards release
+        // the lock before their capacity, so the lock is never contended here.
+        RwWriteGuard {
+            semaphore: self,
+            guard: Some(self.resource.write())
+        }
+    }
+}
+
+/// RAII guard holding shared read access to the resource of a `RwSemaphore`.
+///
+/// Returned from `RwSemaphore::try_read`.
+pub struct RwReadGuard<'a, T: 'a> {
+    semaphore: &'a RwSemaphore<T>,
+    guard: Option<RwLockReadGuard<'a, T>>
+}
+
+impl<'a, T> RwReadGuard<'a, T> {
+    /// Upgrade to exclusive write access, if this is the only active reader.
+    ///
+    /// Otherwise the read guard is handed back unchanged.
+    pub fn upgrade(mut self) -> Result<RwWriteGuard<'a, T>, RwReadGuard<'a, T>> {
+        let semaphore = self.semaphore;
+        if !semaphore.raw.try_upgrade(1, semaphore.raw.capacity()) {
+            return Err(self);
+        }
+        // The capacity is now held by the write guard.
+        self.guard.take();
+        Ok(semaphore.write_guard())
+    }
+}
+
+impl<'a, T> Drop for RwReadGuard<'a, T> {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            drop(guard);
+            self.semaphore.raw.release_n(1);
+        }
+    }
+}
+
+impl<'a, T> Deref for RwReadGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+/// RAII guard holding exclusive write access to the resource of a `RwSemaphore`.
+///
+/// Returned from `RwSemaphore::try_write` and `RwReadGuard::upgrade`.
+pub struct RwWriteGuard<'a, T: 'a> {
+    semaphore: &'a RwSemaphore<T>,
+    guard: Option<RwLockWriteGuard<'a, T>>
+}
+
+impl<'a, T> Drop for RwWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        self.semaphore.raw.release_n(self.semaphore.raw.capacity());
+    }
+}
+
+impl<'a, T> Deref for RwWriteGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<'a, T> DerefMut for RwWriteGuard<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use raw::RawSemaphore;
+use guard;
+use super::{available_cpus, TryAccessError, TryAccessResult};
+
+static NEXT_SHARD_HINT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_HINT: usize = NEXT_SHARD_HINT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counting semaphore that splits its capacity across multiple internal shards.
+///
+/// Every thread is assigned a home shard in round-robin fashion, and will try to acquire
+/// access from that shard first, only scanning the remaining shards when its home shard
+/// is out of capacity. This spreads the atomic traffic of concurrent acquirers over
+/// several cache lines, at the cost of `try_access` having to visit every shard before
+/// it can report that the semaphore is out of capacity.
+///
+/// The total number of processes that can access the underlying resource at the same
+/// time is the sum of all shard capacities, which always equals the requested capacity.
+///
+/// Unlike `Semaphore`, a sharded semaphore can not be shut down.
+pub struct ShardedSemaphore<T> {
+    shards: Arc<Vec<Arc<RawSemaphore>>>,
+    resource: Arc<T>
+}
+
+impl<T> Clone for ShardedSemaphore<T> {
+    fn clone(&self) -> ShardedSemaphore<T> {
+        ShardedSemaphore {
+            shards: self.shards.clone(),
+            resource: self.resource.clone()
+        }
+    }
+}
+
+impl<T> ShardedSemaphore<T> {
+    /// Create a new sharded semaphore around a resource,
+    /// using one shard per available CPU.
+    ///
+    /// The number of shards is capped at the capacity, so that no shard is left empty.
+    pub fn new(capacity: usize, resource: T) -> Self {
+        ShardedSemaphore::with_shards(available_cpus(), capacity, resource)
+    }
+
+    /// Create a new sharded semaphore around a resource, using the given number of shards.
+    ///
+    /// The capacity is distributed as evenly as possible across the shards.
+    /// The number of shards is capped at the capacity, and is at least one.
+    pub fn with_shards(shards: usize, capacity: usize, resource: T) -> Self {
+        let count = shards.min(capacity).max(1);
+        let shards = (0..count).map(|index| {
+            let mut shard_capacity = capacity / count;
+            if index < capacity % count {
+                shard_capacity += 1;
+            }
+            Arc::new(RawSemaphore::new(shard_capacity))
+        }).collect();
+        ShardedSemaphore {
+            shards: Arc::new(shards),
+            resource: Arc::new(resource)
+        }
+    }
+
+    /// Returns the number of shards the capacity has been split into.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    #[inline]
+    /// Attempt to access the underlying resource of this semaphore.
+    ///
+    /// This function will try to acquire access from the home shard of the current thread,
+    /// falling back to the other shards in order, and then return an RAII guard structure
+    /// which will release the access when it falls out of scope.
+    /// If all shards are out of capacity, `TryAccessError::NoCapacity` will be returned.
+    pub fn try_access(&self) -> TryAccessResult<T> {
+        let count = self.shards.len();
+        let start = SHARD_HINT.with(|hint| *hint) % count;
+        for offset in 0..count {
+            let shard = &self.shards[(start + offset) % count];
+            if shard.try_acquire() {
+                return Ok(guard::new(shard, &self.resource));
+            }
+        }
+        Err(TryAccessError::NoCapacity)
+    }
+}
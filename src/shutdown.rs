@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use raw::RawSemaphore;
 
@@ -6,15 +7,15 @@ use raw::RawSemaphore;
 /// allowing for extraction of the underlying resource.
 ///
 /// Returned from `Semaphore::shutdown`. 
-pub struct ShutdownHandle<T> {
+pub struct ShutdownHandle<T: ?Sized> {
     raw: Arc<RawSemaphore>,
     resource: Option<Arc<T>>
 }
 
-pub fn new<T>(raw: &Arc<RawSemaphore>, resource: Option<Arc<T>>) -> ShutdownHandle<T> {
+pub fn new<T: ?Sized>(raw: &Arc<RawSemaphore>, resource: Option<Arc<T>>) -> ShutdownHandle<T> {
     ShutdownHandle {
         raw: raw.clone(),
-        resource: resource
+        resource
     }
 }
 
@@ -25,20 +26,67 @@ impl<T> ShutdownHandle<T> {
     /// When `Semaphore::shutdown` has been called multiple times,
     /// only the first shutdown handle will return the resource.
     /// All others will return `None`.
+    ///
+    /// If references to the resource are still held elsewhere, the resource can not be
+    /// extracted and `None` is returned as well. That is the case if the semaphore was created
+    /// via `Semaphore::from_arc`, or if an `Arc` returned from `Semaphore::try_access_arc` or
+    /// `Semaphore::resource` is still alive. Use `wait_arc` to get the shared resource instead.
     pub fn wait(self) -> Option<T> {
-        self.raw.wait_until_inactive();
-        self.resource.map(|mut arc| {
-            loop {
-                match Arc::try_unwrap(arc) {
-                    Ok(resource) => {
-                        return resource;
-                    },
-                    Err(returned_arc) => {
-                        arc = returned_arc;
-                    }
+        // Guards drop their reference to the resource before releasing their access,
+        // so once the semaphore is inactive only outside references can remain.
+        self.wait_arc().and_then(|arc| Arc::try_unwrap(arc).ok())
+    }
+
+    /// Block until all access has been released to the semaphore like `wait`,
+    /// calling `f` with the number of accesses still held roughly every `interval`.
+    ///
+    /// The callback is not called once all access has been released, so a drain
+    /// that completes within the first interval does not report any progress.
+    pub fn wait_with_progress<F: FnMut(usize)>(self, interval: Duration, f: F) -> Option<T> {
+        self.raw.wait_until_inactive_with(interval, f);
+        self.resource.and_then(|arc| Arc::try_unwrap(arc).ok())
+    }
+}
+
+impl<T: Send + Sync + 'static> ShutdownHandle<T> {
+    /// Hand the underlying resource to the given callback once all access has been released,
+    /// without blocking the current thread.
+    ///
+    /// If there is no active access anymore, the callback runs immediately. Otherwise it runs
+    /// on the thread that releases the last access to the semaphore.
+    ///
+    /// Like `wait`, the callback is only invoked by the first shutdown handle,
+    /// and only when the resource can be extracted.
+    pub fn on_complete<F: FnOnce(T) + Send + 'static>(self, f: F) {
+        if let Some(arc) = self.resource {
+            self.raw.on_shutdown_complete(Box::new(move || {
+                if let Ok(resource) = Arc::try_unwrap(arc) {
+                    f(resource);
                 }
-            }
-        })
+            }));
+        }
+    }
+}
+
+impl<T: ?Sized> ShutdownHandle<T> {
+    /// Block until all access has been released to the semaphore,
+    /// and return the shared reference to the underlying resource.
+    ///
+    /// Like `wait`, only the first shutdown handle will return the resource.
+    pub fn wait_arc(self) -> Option<Arc<T>> {
+        self.raw.wait_until_inactive();
+        self.resource
+    }
+
+    #[inline]
+    /// Whether this handle is the one that received the resource, without blocking.
+    ///
+    /// Of all concurrent and subsequent calls to `Semaphore::shutdown`, exactly one takes
+    /// the resource out of the semaphore, and whichever does so first in the order of
+    /// that operation wins. Only the winning handle returns the resource from `wait_arc`,
+    /// and from `wait`, as far as the resource can be extracted.
+    pub fn won_resource(&self) -> bool {
+        self.resource.is_some()
     }
 
     #[doc(hidden)]
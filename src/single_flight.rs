@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::{AcquireError, Semaphore};
+
+/// Semaphore that coalesces concurrent computations for the same key.
+///
+/// The first request for a key acquires access and runs its computation, while requests
+/// for the same key arriving before it has finished wait for its result instead of taking
+/// access of their own. Requests for different keys are limited by the capacity as usual.
+/// Results are not cached: once a computation has finished, the next request for its key
+/// starts a new one.
+pub struct SingleFlightSemaphore<K, V> {
+    semaphore: Semaphore<()>,
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>
+}
+
+/// Computation in flight, which the requests joining it wait on.
+struct Flight<V> {
+    state: Mutex<State<V>>,
+    cond: Condvar
+}
+
+enum State<V> {
+    Pending,
+    Done(Result<Arc<V>, AcquireError>)
+}
+
+impl<V> Flight<V> {
+    fn finish(&self, result: Result<Arc<V>, AcquireError>) {
+        *self.state.lock() = State::Done(result);
+        self.cond.notify_all();
+    }
+
+    fn wait(&self) -> Result<Arc<V>, AcquireError> {
+        let mut state = self.state.lock();
+        loop {
+            if let State::Done(ref result) = *state {
+                return result.clone();
+            }
+            self.cond.wait(&mut state);
+        }
+    }
+}
+
+/// Finishes a flight and removes it, even if the computation panics.
+struct Landing<'a, K: 'a + Eq + Hash, V: 'a> {
+    flights: &'a Mutex<HashMap<K, Arc<Flight<V>>>>,
+    key: Option<K>,
+    flight: Arc<Flight<V>>,
+    result: Option<Result<Arc<V>, AcquireError>>
+}
+
+impl<'a, K: Eq + Hash, V> Drop for Landing<'a, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.flights.lock().remove(&key);
+        }
+        // Requests that joined a computation which panicked are told so, instead of waiting forever.
+        let result = self.result.take().unwrap_or(Err(AcquireError::Poisoned));
+        self.flight.finish(result);
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> SingleFlightSemaphore<K, V> {
+    /// Create a new single-flight semaphore, running up to `capacity` computations at the same time.
+    pub fn new(capacity: usize) -> Self {
+        SingleFlightSemaphore {
+            semaphore: Semaphore::new(capacity, ()),
+            flights: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Get the result of `compute` for `key`, sharing the computation with concurrent requests.
+    ///
+    /// If a computation for `key` is already in flight, this blocks until it has finished and
+    /// returns its result, without acquiring access or calling `compute`. Otherwise this blocks
+    /// until access is available like `Semaphore::access`, and then runs `compute` while
+    /// holding it. If acquiring access fails, all requests of the flight receive the error.
+    /// If `compute` panics, the requests that joined it fail with `AcquireError::Poisoned`.
+    pub fn access<F: FnOnce() -> V>(&self, key: K, compute: F) -> Result<Arc<V>, AcquireError> {
+        let flight = {
+            let mut flights = self.flights.lock();
+            if let Some(flight) = flights.get(&key) {
+                let flight = flight.clone();
+                drop(flights);
+                return flight.wait();
+            }
+            let flight = Arc::new(Flight {
+                state: Mutex::new(State::Pending),
+                cond: Condvar::new()
+            });
+            flights.insert(key.clone(), flight.clone());
+            flight
+        };
+        let mut landing = Landing {
+            flights: &self.flights,
+            key: Some(key),
+            flight,
+            result: None
+        };
+        let result = self.semaphore.access().map(|_guard| Arc::new(compute()));
+        landing.result = Some(result.clone());
+        result
+    }
+
+    #[inline]
+    /// Number of computations that are currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().len()
+    }
+
+    #[inline]
+    /// Borrow the underlying semaphore, for example to read its snapshot.
+    pub fn as_semaphore(&self) -> &Semaphore<()> {
+        &self.semaphore
+    }
+}
@@ -0,0 +1,19 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Point-in-time view of the state of a semaphore.
+///
+/// Returned from `Semaphore::snapshot`. The `capacity`, `active` and `available` fields are
+/// consistent with each other: they describe the semaphore at a single point in time, even
+/// while the capacity is being changed concurrently. The `waiters` and `is_shutdown` fields
+/// are read right after, and may already have moved on relative to the others.
+pub struct SemaphoreSnapshot {
+    /// Maximum number of accesses the semaphore grants at the same time.
+    pub capacity: usize,
+    /// Number of accesses that are held.
+    pub active: usize,
+    /// Number of accesses that can be granted before the semaphore is at capacity.
+    pub available: usize,
+    /// Number of acquirers blocked waiting for capacity.
+    pub waiters: usize,
+    /// Whether the semaphore has been closed or shut down.
+    pub is_shutdown: bool
+}
@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+/// Summary of the time blocked acquirers had to wait before they were granted access.
+///
+/// Returned from `Semaphore::wait_stats`. Only acquisitions that actually blocked are
+/// recorded, those that found capacity available right away are not.
+pub struct WaitStats {
+    /// Number of acquisitions that blocked before being granted access.
+    pub count: u64,
+    /// Total time spent waiting across all of those acquisitions.
+    pub total: Duration,
+    /// Longest time a single acquisition had to wait.
+    pub max: Duration
+}
+
+impl WaitStats {
+    /// Average time an acquisition that blocked had to wait, if any did.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos((self.total.as_nanos() / u128::from(self.count)) as u64))
+    }
+}
+
+/// Lock-free accumulator behind `WaitStats`.
+#[derive(Default)]
+pub struct WaitRecorder {
+    count: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64
+}
+
+impl WaitRecorder {
+    pub fn record(&self, waited: Duration) {
+        let nanos = waited.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> WaitStats {
+        WaitStats {
+            count: self.count.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed))
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Strategies deciding how `Semaphore::access_with` behaves when the semaphore is at capacity.
+
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use raw::RawSemaphore;
+
+/// Policy applied by `Semaphore::access_with` whenever an acquisition attempt
+/// fails because the semaphore is out of capacity.
+///
+/// Implement this trait to provide custom acquisition behaviour.
+pub trait AcquireStrategy {
+    /// Decide whether another acquisition attempt should be made.
+    ///
+    /// Called after every failed attempt. The strategy may block, spin or otherwise
+    /// delay before returning. Returning `false` gives up, which will turn into a
+    /// `TryAccessError::NoCapacity` error.
+    fn retry(&mut self, raw: &RawSemaphore) -> bool;
+}
+
+impl<S: AcquireStrategy + ?Sized> AcquireStrategy for &mut S {
+    #[inline]
+    fn retry(&mut self, raw: &RawSemaphore) -> bool {
+        (**self).retry(raw)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+/// Give up as soon as the semaphore is found to be at capacity.
+///
+/// This is the strategy used by `Semaphore::try_access`.
+pub struct Immediate;
+
+impl AcquireStrategy for Immediate {
+    #[inline]
+    fn retry(&mut self, _raw: &RawSemaphore) -> bool {
+        false
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Busy-wait for up to the given number of additional attempts before giving up.
+pub struct Spin(pub usize);
+
+impl AcquireStrategy for Spin {
+    #[inline]
+    fn retry(&mut self, _raw: &RawSemaphore) -> bool {
+        if self.0 == 0 {
+            return false;
+        }
+        self.0 -= 1;
+        hint::spin_loop();
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Sleep for a fixed duration between attempts, giving up after the given number
+/// of additional attempts.
+pub struct FixedBackoff(pub Duration, pub usize);
+
+impl AcquireStrategy for FixedBackoff {
+    fn retry(&mut self, _raw: &RawSemaphore) -> bool {
+        if self.1 == 0 {
+            return false;
+        }
+        self.1 -= 1;
+        thread::sleep(self.0);
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Sleep between attempts, doubling the delay after every attempt up to a maximum,
+/// and giving up after the given number of additional attempts.
+pub struct ExponentialBackoff {
+    delay: Duration,
+    max_delay: Duration,
+    attempts: usize
+}
+
+impl ExponentialBackoff {
+    /// Start with the given delay, and never sleep for longer than `max_delay` at a time.
+    pub fn new(delay: Duration, max_delay: Duration, attempts: usize) -> ExponentialBackoff {
+        ExponentialBackoff {
+            delay: delay.min(max_delay),
+            max_delay,
+            attempts
+        }
+    }
+}
+
+impl AcquireStrategy for ExponentialBackoff {
+    fn retry(&mut self, _raw: &RawSemaphore) -> bool {
+        if self.attempts == 0 {
+            return false;
+        }
+        self.attempts -= 1;
+        thread::sleep(self.delay);
+        self.delay = self.delay.saturating_mul(2).min(self.max_delay);
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+/// Block the current thread until capacity becomes available.
+///
+/// Waits via `RawSemaphore::wait`, which wakes all blocked threads whenever capacity
+/// becomes available. `Semaphore::access` instead queues acquirers individually,
+/// and should be preferred when many threads block at the same time.
+pub struct Block;
+
+impl AcquireStrategy for Block {
+    fn retry(&mut self, raw: &RawSemaphore) -> bool {
+        raw.wait();
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Block the current thread until capacity becomes available,
+/// giving up once the given duration has elapsed in total.
+///
+/// Like `Block`, this wakes all blocked threads whenever capacity becomes available.
+/// See `Semaphore::access_timeout` for an alternative that wakes them individually.
+pub struct BlockTimeout(pub Duration);
+
+impl AcquireStrategy for BlockTimeout {
+    fn retry(&mut self, raw: &RawSemaphore) -> bool {
+        let start = Instant::now();
+        let available = raw.wait_timeout(self.0);
+        self.0 = self.0.saturating_sub(start.elapsed());
+        available
+    }
+}
@@ -1,4 +1,9 @@
-use super::{Semaphore, TryAccessError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::{AcquireError, ConstSemaphore, LimitState, LocalSemaphore, RawSemaphore, RwSemaphore, Semaphore, ShardedSemaphore, TryAccessError, TryTakeError};
+use super::strategy::{AcquireStrategy, Block, ExponentialBackoff, FixedBackoff, Spin};
 
 #[test]
 fn succeeds_to_acquire_when_empty() {
@@ -14,14 +19,18 @@ fn fails_to_acquire_when_full() {
     }).collect::<Vec<_>>();
     assert_eq!(sema.try_access().err().unwrap(),
         TryAccessError::NoCapacity);
+    assert_eq!(4, sema.active_count());
     drop(guards);
+    assert_eq!(0, sema.active_count());
 }
 
 #[test]
 fn dropping_guard_frees_capacity() {
     let sema = Semaphore::new(1, ());
     let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(1, sema.active_count());
     drop(guard);
+    assert_eq!(0, sema.active_count());
     assert!(sema.try_access().ok().is_some());
 }
 
@@ -34,6 +43,7 @@ fn fails_to_acquire_when_shut_down() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn shutdown_complete_when_empty() {
     let sema = Semaphore::new(1, ());
     let handle = sema.shutdown();
@@ -42,6 +52,7 @@ fn shutdown_complete_when_empty() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn shutdown_complete_when_guard_drops() {
     let sema = Semaphore::new(1, ());
     let guard = sema.try_access().expect("guard acquisition failed");
@@ -62,3 +73,2468 @@ fn first_shutdown_can_extract_resource() {
     assert_eq!(Some(()), first_handle.wait());
     assert_eq!(None, third_handle.wait());
 }
+
+#[test]
+fn sharded_distributes_capacity_across_shards() {
+    let sema = ShardedSemaphore::with_shards(3, 7, ());
+    assert_eq!(3, sema.shard_count());
+    let guards = (0..7).map(|_| {
+        sema.try_access().expect("guard acquisition failed")
+    }).collect::<Vec<_>>();
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guards);
+    assert!(sema.try_access().ok().is_some());
+}
+
+#[test]
+fn sharded_caps_shards_at_capacity() {
+    let sema = ShardedSemaphore::with_shards(8, 2, ());
+    assert_eq!(2, sema.shard_count());
+    let empty = ShardedSemaphore::with_shards(8, 0, ());
+    assert_eq!(1, empty.shard_count());
+    assert_eq!(empty.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+}
+
+#[test]
+fn spin_strategy_gives_up_when_full() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.access_with(Spin(3)).err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guard);
+    assert!(sema.access_with(Spin(3)).ok().is_some());
+}
+
+#[test]
+fn custom_strategy_is_retried_until_it_gives_up() {
+    struct CountAttempts(usize);
+
+    impl AcquireStrategy for CountAttempts {
+        fn retry(&mut self, _raw: &RawSemaphore) -> bool {
+            self.0 += 1;
+            self.0 < 5
+        }
+    }
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let mut strategy = CountAttempts(0);
+    assert_eq!(sema.access_with(&mut strategy).err().unwrap(),
+        TryAccessError::NoCapacity);
+    assert_eq!(5, strategy.0);
+    drop(guard);
+}
+
+#[test]
+fn access_blocks_until_guard_drops() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        drop(guard);
+    });
+    assert!(sema.access().ok().is_some());
+    releaser.join().unwrap();
+}
+
+#[test]
+fn access_timeout_expires_when_full() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.access_timeout(Duration::from_millis(10)).err().unwrap(),
+        AcquireError::Timeout);
+    drop(guard);
+    assert!(sema.access_timeout(Duration::from_millis(10)).ok().is_some());
+}
+
+#[test]
+fn access_fails_when_shut_down() {
+    let sema = Semaphore::new(1, ());
+    sema.shutdown();
+    assert_eq!(sema.access().err().unwrap(),
+        AcquireError::Shutdown);
+}
+
+#[test]
+fn shutdown_extracts_resource_when_guards_drop_concurrently() {
+    for _ in 0..100 {
+        let sema = Semaphore::new(4, ());
+        let guards = (0..4).map(|_| {
+            sema.try_access().expect("guard acquisition failed")
+        }).collect::<Vec<_>>();
+        let handle = sema.shutdown();
+        let threads = guards.into_iter().map(|guard| {
+            thread::spawn(move || drop(guard))
+        }).collect::<Vec<_>>();
+        assert_eq!(Some(()), handle.wait());
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn from_arc_shares_the_resource() {
+    let resource = Arc::new(42);
+    let sema = Semaphore::from_arc(1, resource.clone());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert!(::std::ptr::eq(&*resource, &*guard));
+    drop(guard);
+    let handle = sema.shutdown();
+    let extracted = handle.wait_arc().expect("resource missing");
+    assert!(Arc::ptr_eq(&resource, &extracted));
+}
+
+#[test]
+fn from_arc_wait_fails_while_shared() {
+    let resource = Arc::new(42);
+    let sema = Semaphore::from_arc(1, resource.clone());
+    assert_eq!(None, sema.shutdown().wait());
+    drop(resource);
+    let sema = Semaphore::from_arc(1, Arc::new(42));
+    assert_eq!(Some(42), sema.shutdown().wait());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn dropping_pending_acquire_future_deregisters_waker() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Waker};
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut future = sema.acquire_async();
+    assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+    assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+    assert_eq!(1, sema.inner.raw.registered_wakers());
+    drop(future);
+    assert_eq!(0, sema.inner.raw.registered_wakers());
+
+    drop(guard);
+    let guard = sema.try_access().expect("permit was leaked");
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guard);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn acquire_future_completes_after_release() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut future = sema.acquire_async();
+    assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+    drop(guard);
+    assert_eq!(0, sema.inner.raw.registered_wakers());
+    match Pin::new(&mut future).poll(&mut cx) {
+        Poll::Ready(result) => assert!(result.is_ok()),
+        Poll::Pending => panic!("future still pending after release")
+    }
+}
+
+#[cfg(feature = "debug-holders")]
+#[test]
+fn outstanding_lists_acquisition_sites_of_live_guards() {
+    let sema = Semaphore::new(3, ());
+    let first_line = line!() + 1;
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second_line = line!() + 1;
+    let second = sema.access().expect("guard acquisition failed");
+    let sites = sema.outstanding();
+    assert_eq!(2, sites.len());
+    assert_eq!(file!(), sites[0].file());
+    assert_eq!(first_line, sites[0].line());
+    assert_eq!(second_line, sites[1].line());
+    drop(first);
+    let sites = sema.outstanding();
+    assert_eq!(1, sites.len());
+    assert_eq!(second_line, sites[0].line());
+    drop(second);
+    assert!(sema.outstanding().is_empty());
+}
+
+#[test]
+fn clones_are_equal() {
+    let sema = Semaphore::new(1, ());
+    let clone = sema.clone();
+    assert!(sema == clone);
+}
+
+#[test]
+fn separately_constructed_semaphores_are_not_equal() {
+    let resource = Arc::new(());
+    let first = Semaphore::from_arc(1, resource.clone());
+    let second = Semaphore::from_arc(1, resource);
+    assert!(first != second);
+}
+
+#[test]
+fn fair_access_with_elapsed_timeout_takes_available_capacity() {
+    let sema = Semaphore::new(1, ());
+    assert!(sema.access_fair_timeout(Duration::from_millis(0)).ok().is_some());
+}
+
+#[test]
+fn timed_out_fair_acquirer_leaves_the_queue() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.access_fair_timeout(Duration::from_millis(10)).err().unwrap(),
+        AcquireError::Timeout);
+    let patient = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_fair_timeout(Duration::from_secs(10)).is_ok())
+    };
+    drop(guard);
+    assert!(patient.join().unwrap());
+}
+
+#[test]
+fn fair_timeout_racing_release_never_loses_capacity() {
+    for _ in 0..50 {
+        let sema = Semaphore::new(1, ());
+        let guard = sema.try_access().expect("guard acquisition failed");
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(1));
+            drop(guard);
+        });
+        let result = sema.access_fair_timeout(Duration::from_millis(1));
+        releaser.join().unwrap();
+        match result {
+            Ok(guard) => {
+                assert!(sema.try_access().is_err());
+                drop(guard);
+            },
+            Err(err) => assert_eq!(AcquireError::Timeout, err)
+        }
+        assert_eq!(0, sema.active_count());
+        assert!(sema.try_access().ok().is_some());
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn permit_stream_yields_guards_as_capacity_returns() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    let sema = Semaphore::new(2, ());
+    let mut stream = sema.permit_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut guards = Vec::new();
+    for _ in 0..2 {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(guard)) => guards.push(guard),
+            _ => panic!("stream did not yield a guard")
+        }
+    }
+    assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+    guards.pop();
+    assert!(matches!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(Some(_))));
+
+    drop(guards);
+    drop(sema.shutdown());
+    assert!(matches!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(None)));
+}
+
+#[test]
+fn release_burst_notifies_blocked_acquirer_once() {
+    let sema = Semaphore::new(8, ());
+    let mut guards = (0..8).map(|_| {
+        sema.try_access().expect("guard acquisition failed")
+    }).collect::<Vec<_>>();
+    let last = guards.pop().unwrap();
+    let (tx, rx) = ::std::sync::mpsc::channel::<()>();
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            let guard = sema.access_with(Block).expect("guard acquisition failed");
+            rx.recv().unwrap();
+            drop(guard);
+        })
+    };
+    while sema.inner.raw.waiter_count() == 0 {
+        thread::yield_now();
+    }
+    drop(guards);
+    assert_eq!(1, sema.inner.raw.notification_count());
+    tx.send(()).unwrap();
+    waiter.join().unwrap();
+    drop(last);
+    assert_eq!(2, sema.inner.raw.notification_count());
+}
+
+#[test]
+fn try_access_arc_shares_the_guarded_allocation() {
+    let sema = Semaphore::new(1, 42);
+    let (guard, resource) = sema.try_access_arc().expect("guard acquisition failed");
+    assert!(::std::ptr::eq(&*guard, &*resource));
+    assert_eq!(sema.try_access_arc().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guard);
+    assert_eq!(42, *resource);
+}
+
+#[test]
+fn unsized_slice_resource() {
+    let resource: Arc<[u8]> = Arc::from(vec![1, 2, 3]);
+    let sema: Semaphore<[u8]> = Semaphore::from_arc(1, resource);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(&[1, 2, 3], &*guard);
+    drop(guard);
+    assert_eq!(3, sema.shutdown().wait_arc().expect("resource missing").len());
+}
+
+#[test]
+fn unsized_trait_object_resource() {
+    let resource: Arc<dyn Fn() -> u32 + Send + Sync> = Arc::new(|| 42);
+    let sema = Semaphore::from_arc(1, resource);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(42, guard());
+}
+
+#[test]
+fn permit_guard_counts_against_capacity() {
+    let sema = Semaphore::new(1, ());
+    let permit = sema.try_acquire_permit().expect("permit acquisition failed");
+    assert_eq!(1, sema.active_count());
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(permit);
+    assert_eq!(0, sema.active_count());
+    assert!(sema.try_access().ok().is_some());
+}
+
+#[test]
+fn permit_guard_fails_when_shut_down() {
+    let sema = Semaphore::new(1, ());
+    let permit = sema.try_acquire_permit().expect("permit acquisition failed");
+    let handle = sema.shutdown();
+    assert!(!handle.is_complete());
+    assert_eq!(sema.try_acquire_permit().err().unwrap(),
+        TryAccessError::Shutdown);
+    drop(permit);
+    assert_eq!(Some(()), handle.wait());
+}
+
+#[test]
+fn on_complete_receives_resource_when_last_guard_releases() {
+    let sema = Semaphore::new(2, 42);
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    sema.shutdown().on_complete(move |resource| tx.send(resource).unwrap());
+    drop(first);
+    assert!(rx.try_recv().is_err());
+    drop(second);
+    assert_eq!(Ok(42), rx.try_recv());
+}
+
+#[test]
+fn on_complete_runs_immediately_when_inactive() {
+    let sema = Semaphore::new(1, 42);
+    let (tx, rx) = ::std::sync::mpsc::channel();
+    sema.shutdown().on_complete(move |resource| tx.send(resource).unwrap());
+    assert_eq!(Ok(42), rx.try_recv());
+}
+
+#[test]
+fn try_access_with_headroom_reports_remaining_capacity() {
+    let sema = Semaphore::new(3, ());
+    let guards = (0..3).map(|expected| {
+        let (guard, headroom) = sema.try_access_with_headroom()
+            .expect("guard acquisition failed");
+        assert_eq!(2 - expected, headroom);
+        guard
+    }).collect::<Vec<_>>();
+    assert_eq!(sema.try_access_with_headroom().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guards);
+}
+
+#[test]
+fn close_unblocks_blocked_acquirers() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let waiters = (0..2).map(|index| {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            if index == 0 {
+                sema.access().err()
+            } else {
+                sema.access_fair().err()
+            }
+        })
+    }).collect::<Vec<_>>();
+    while sema.inner.raw.queued_count() + sema.inner.raw.waiter_count() < 2 {
+        thread::yield_now();
+    }
+    sema.close();
+    for waiter in waiters {
+        assert_eq!(Some(AcquireError::Shutdown), waiter.join().unwrap());
+    }
+    drop(guard);
+}
+
+#[test]
+fn close_keeps_the_resource_for_shutdown() {
+    let sema = Semaphore::new(1, 42);
+    sema.close();
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::Shutdown);
+    assert_eq!(sema.try_acquire_permit().err().unwrap(),
+        TryAccessError::Shutdown);
+    assert_eq!(Some(42), sema.shutdown().wait());
+}
+
+#[test]
+fn into_inner_succeeds_for_sole_owner() {
+    let sema = Semaphore::new(1, 42);
+    drop(sema.try_access().expect("guard acquisition failed"));
+    assert_eq!(Ok(42), sema.into_inner().map_err(|_| ()));
+}
+
+#[test]
+fn into_inner_fails_while_shared() {
+    let sema = Semaphore::new(1, 42);
+    let clone = sema.clone();
+    let sema = sema.into_inner().expect_err("succeeded despite clone");
+    drop(clone);
+
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let sema = sema.into_inner().expect_err("succeeded despite guard");
+    drop(guard);
+
+    let resource = Arc::new(42);
+    let shared = Semaphore::from_arc(1, resource.clone());
+    let shared = shared.into_inner().expect_err("succeeded despite shared resource");
+    assert!(shared.try_access().is_ok());
+
+    assert_eq!(Ok(42), sema.into_inner().map_err(|_| ()));
+}
+
+#[test]
+fn access_n_timeout_releases_nothing_on_timeout() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let sema = Semaphore::new(3, ());
+    let running = Arc::new(AtomicBool::new(true));
+    let churners = (0..2).map(|_| {
+        let sema = sema.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                if let Ok(guard) = sema.try_access() {
+                    thread::yield_now();
+                    drop(guard);
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    // Keep one access held, so that the weighted acquisition can never succeed.
+    let held = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.access_n_timeout(3, Duration::from_millis(50)).err().unwrap(),
+        AcquireError::Timeout);
+    drop(held);
+
+    for _ in 0..20 {
+        if let Ok(guard) = sema.access_n_timeout(2, Duration::from_millis(10)) {
+            assert_eq!(2, guard.permits());
+        }
+    }
+
+    running.store(false, Ordering::SeqCst);
+    for churner in churners {
+        churner.join().unwrap();
+    }
+    assert_eq!(0, sema.active_count());
+    let guard = sema.try_access_n(3).expect("accesses leaked");
+    assert_eq!(3, guard.permits());
+    assert_eq!(3, sema.active_count());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    assert_eq!(sema.access_n_timeout(4, Duration::from_millis(1)).err().unwrap(),
+        AcquireError::NoCapacity);
+}
+
+#[test]
+fn blocking_methods_report_shutdown_as_acquire_error() {
+    let sema = Semaphore::new(2, ());
+    sema.close();
+    let timeout = Duration::from_millis(10);
+    assert!(sema.access().err().unwrap().is_shutdown());
+    assert!(sema.access_timeout(timeout).err().unwrap().is_shutdown());
+    assert!(sema.access_fair().err().unwrap().is_shutdown());
+    assert!(sema.access_fair_timeout(timeout).err().unwrap().is_shutdown());
+    assert!(sema.access_n_timeout(2, timeout).err().unwrap().is_shutdown());
+}
+
+#[test]
+fn acquire_error_converts_into_try_access_error() {
+    assert_eq!(TryAccessError::Shutdown, TryAccessError::from(AcquireError::Shutdown));
+    assert_eq!(TryAccessError::NoCapacity, TryAccessError::from(AcquireError::Timeout));
+    assert_eq!(TryAccessError::NoCapacity, TryAccessError::from(AcquireError::Cancelled));
+    assert!(AcquireError::Cancelled.is_cancelled());
+    assert!(!AcquireError::Timeout.is_cancelled());
+}
+
+#[test]
+fn mapped_semaphore_projects_resource_and_shares_capacity() {
+    let sema = Semaphore::new(2, vec![1, 2, 3]);
+    let mapped = sema.map(|numbers| numbers.iter().sum::<i32>());
+    let first = mapped.try_access().expect("guard acquisition failed");
+    assert_eq!(6, *first);
+    let second = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(mapped.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(first);
+    assert_eq!(6, *mapped.access().expect("guard acquisition failed"));
+    drop(second);
+    sema.close();
+    assert_eq!(mapped.try_access().err().unwrap(), TryAccessError::Shutdown);
+}
+
+#[test]
+fn handle_count_tracks_clones_and_guards() {
+    let sema = Semaphore::new(1, ());
+    assert_eq!(1, sema.handle_count());
+    let clones = (0..3).map(|_| sema.clone()).collect::<Vec<_>>();
+    assert_eq!(4, sema.handle_count());
+    let guard = clones[0].try_access().expect("guard acquisition failed");
+    assert_eq!(5, sema.handle_count());
+    drop(clones);
+    drop(guard);
+    assert_eq!(1, sema.handle_count());
+}
+
+#[test]
+fn guards_moved_into_workers_never_exceed_capacity() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn assert_send_static<G: Send + 'static>(_: &G) {}
+
+    let sema = Semaphore::new(3, AtomicUsize::new(0));
+    let max_active = Arc::new(AtomicUsize::new(0));
+    let workers = (0..12).map(|_| {
+        let guard = sema.access().expect("guard acquisition failed");
+        assert_send_static(&guard);
+        let max_active = max_active.clone();
+        thread::spawn(move || {
+            let active = guard.fetch_add(1, Ordering::SeqCst) + 1;
+            max_active.fetch_max(active, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(2));
+            guard.fetch_sub(1, Ordering::SeqCst);
+        })
+    }).collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert!(max_active.load(Ordering::SeqCst) <= 3);
+    assert_eq!(0, sema.active_count());
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn peak_active_tracks_highest_concurrent_access() {
+    let sema = Semaphore::new(4, ());
+    assert_eq!(0, sema.peak_active());
+    let guards = (0..3).map(|_| {
+        sema.try_access().expect("guard acquisition failed")
+    }).collect::<Vec<_>>();
+    drop(guards);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(3, sema.peak_active());
+    sema.reset_peak();
+    assert_eq!(1, sema.peak_active());
+    drop(guard);
+}
+
+#[test]
+fn poll_acquire_registers_task_until_release() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+    let sema = Semaphore::new(1, ());
+
+    let guard = match sema.poll_acquire(&mut cx) {
+        Poll::Ready(result) => result.expect("guard acquisition failed"),
+        Poll::Pending => panic!("pending despite available capacity")
+    };
+    assert!(sema.poll_acquire(&mut cx).is_pending());
+    assert!(sema.poll_acquire(&mut cx).is_pending());
+    assert_eq!(1, sema.inner.raw.registered_wakers());
+
+    drop(guard);
+    assert_eq!(1, wakes.0.load(Ordering::SeqCst));
+    assert_eq!(0, sema.inner.raw.registered_wakers());
+    assert!(sema.poll_acquire(&mut cx).is_ready());
+}
+
+#[test]
+fn for_cpus_has_at_least_one_unit_of_capacity() {
+    let cpus = Semaphore::for_cpus(());
+    assert!(cpus.capacity() >= 1);
+    assert_eq!(cpus.capacity() * 4, Semaphore::for_cpus_scaled((), 4).capacity());
+    assert_eq!(1, Semaphore::for_cpus_scaled((), 0).capacity());
+}
+
+#[test]
+fn release_wakes_blocked_acquirers_one_at_a_time_in_order() {
+    use std::sync::mpsc;
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let (tx, rx) = mpsc::channel();
+    let waiters = (0..3).map(|index| {
+        let waiter = {
+            let sema = sema.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let guard = sema.access().expect("guard acquisition failed");
+                tx.send((index, guard)).unwrap();
+            })
+        };
+        while sema.inner.raw.queued_count() <= index {
+            thread::yield_now();
+        }
+        waiter
+    }).collect::<Vec<_>>();
+
+    drop(guard);
+    for expected in 0..3 {
+        let (index, guard) = rx.recv().unwrap();
+        assert_eq!(expected, index);
+        // Only the acquirer at the front was woken, the others are still queued.
+        assert_eq!(2 - expected, sema.inner.raw.queued_count());
+        drop(guard);
+    }
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn weighted_acquirer_at_the_front_keeps_its_position() {
+    let sema = Semaphore::new(2, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    let weighted = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_n_timeout(2, Duration::from_secs(10)).map(|guard| guard.permits()))
+    };
+    while sema.inner.raw.queued_count() == 0 {
+        thread::yield_now();
+    }
+    let single = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_timeout(Duration::from_secs(10)).is_ok())
+    };
+    while sema.inner.raw.queued_count() < 2 {
+        thread::yield_now();
+    }
+    drop(first);
+    // The single acquirer queued behind the weighted one is not woken to overtake it.
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(2, sema.inner.raw.queued_count());
+    drop(second);
+    assert_eq!(Ok(2), weighted.join().unwrap());
+    assert!(single.join().unwrap());
+}
+
+#[test]
+fn tagged_guard_reports_its_tag() {
+    let sema = Semaphore::new(2, ());
+    let tagged = sema.try_access_tagged("request-42").expect("guard acquisition failed");
+    let owned = sema.try_access_tagged(format!("request-{}", 43)).expect("guard acquisition failed");
+    assert_eq!(Some("request-42"), tagged.tag());
+    assert_eq!(Some("request-43"), owned.tag());
+    drop(tagged);
+    assert_eq!(None, sema.try_access().expect("guard acquisition failed").tag());
+}
+
+#[test]
+fn wait_for_capacity_returns_once_guard_drops() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            sema.wait_for_capacity();
+            sema.try_access().is_ok()
+        })
+    };
+    while sema.inner.raw.waiter_count() == 0 {
+        thread::yield_now();
+    }
+    drop(guard);
+    assert!(waiter.join().unwrap());
+}
+
+#[test]
+fn rw_semaphore_allows_concurrent_readers_up_to_capacity() {
+    let sema = RwSemaphore::new(2, 42);
+    let first = sema.try_read().expect("read acquisition failed");
+    let second = sema.try_read().expect("read acquisition failed");
+    assert_eq!(42, *first);
+    assert_eq!(42, *second);
+    assert_eq!(sema.try_read().err().unwrap(), TryAccessError::NoCapacity);
+    drop(first);
+    assert_eq!(sema.try_write().err().unwrap(), TryAccessError::NoCapacity);
+    drop(second);
+    assert!(sema.try_write().is_ok());
+}
+
+#[test]
+fn rw_semaphore_writer_is_exclusive() {
+    let sema = RwSemaphore::new(4, 1);
+    {
+        let mut writer = sema.try_write().expect("write acquisition failed");
+        assert_eq!(sema.try_read().err().unwrap(), TryAccessError::NoCapacity);
+        assert_eq!(sema.try_write().err().unwrap(), TryAccessError::NoCapacity);
+        *writer += 1;
+    }
+    assert_eq!(2, *sema.try_read().expect("read acquisition failed"));
+}
+
+#[test]
+fn rw_read_guard_upgrades_only_as_sole_reader() {
+    let sema = RwSemaphore::new(2, 1);
+    let first = sema.try_read().expect("read acquisition failed");
+    let second = sema.try_read().expect("read acquisition failed");
+    let first = first.upgrade().err().expect("upgraded despite another reader");
+    drop(second);
+    let mut writer = first.upgrade().ok().expect("upgrade as sole reader failed");
+    assert_eq!(sema.try_read().err().unwrap(), TryAccessError::NoCapacity);
+    *writer = 2;
+    drop(writer);
+    assert_eq!(2, *sema.try_read().expect("read acquisition failed"));
+}
+
+#[test]
+fn blocking_access_fails_fast_without_capacity() {
+    let sema = Semaphore::new(0, ());
+    let timeout = Duration::from_secs(10);
+    assert_eq!(AcquireError::NoCapacity, sema.access().err().unwrap());
+    assert_eq!(AcquireError::NoCapacity, sema.access_timeout(timeout).err().unwrap());
+    assert_eq!(AcquireError::NoCapacity, sema.access_fair().err().unwrap());
+    assert_eq!(AcquireError::NoCapacity, sema.access_fair_timeout(timeout).err().unwrap());
+}
+
+#[test]
+fn map_mut_projects_a_field_of_an_exclusive_resource() {
+    struct Counters {
+        hits: usize,
+        misses: usize
+    }
+
+    let sema = Semaphore::new(1, Counters { hits: 0, misses: 0 });
+    {
+        let guard = sema.try_access().expect("guard acquisition failed");
+        let mut hits = guard.map_mut(|counters| &mut counters.hits).ok().expect("projection failed");
+        *hits += 1;
+        assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    }
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!((1, 0), (guard.hits, guard.misses));
+    drop(guard);
+
+    let shared = Semaphore::new(2, 0);
+    let guard = shared.try_access().expect("guard acquisition failed");
+    assert!(guard.map_mut(|value| value).is_err());
+}
+
+#[test]
+fn drain_and_shutdown_waits_for_in_flight_work() {
+    let sema = Semaphore::new(2, 42);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let worker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        drop(guard);
+    });
+    assert_eq!(Some(42), sema.drain_and_shutdown());
+    worker.join().unwrap();
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::Shutdown);
+    assert_eq!(None, sema.drain_and_shutdown());
+}
+
+#[test]
+fn guards_record_acquisition_order() {
+    let sema = Semaphore::new(3, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.access().expect("guard acquisition failed");
+    drop(sema.try_acquire_permit().expect("permit acquisition failed"));
+    let third = sema.try_access_n(1).expect("guard acquisition failed");
+    assert_eq!((0, 1, 2), (first.sequence(), second.sequence(), third.sequence()));
+    drop(first);
+    assert_eq!(3, sema.try_access().expect("guard acquisition failed").sequence());
+}
+
+#[test]
+fn const_semaphore_acquires_many_within_capacity() {
+    let sema = ConstSemaphore::<_, 3>::new(());
+    let guard = sema.try_access_many::<2>().expect("guard acquisition failed");
+    assert_eq!(2, guard.permits());
+    assert_eq!(sema.try_access_many::<2>().err().unwrap(), TryAccessError::NoCapacity);
+    assert!(sema.as_semaphore().try_access().is_ok());
+}
+
+#[test]
+fn backoff_strategies_retry_until_capacity_returns() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let backoff = FixedBackoff(Duration::from_millis(1), 3);
+    assert_eq!(sema.access_with(backoff).err().unwrap(), TryAccessError::NoCapacity);
+    let backoff = ExponentialBackoff::new(Duration::from_millis(1), Duration::from_millis(4), 3);
+    assert_eq!(sema.access_with(backoff).err().unwrap(), TryAccessError::NoCapacity);
+
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(5));
+        drop(guard);
+    });
+    let backoff = ExponentialBackoff::new(Duration::from_millis(1), Duration::from_millis(10), 100);
+    assert!(sema.access_with(backoff).is_ok());
+    releaser.join().unwrap();
+}
+
+#[test]
+fn exactly_one_racing_clone_wins_the_shutdown() {
+    use std::sync::Barrier;
+
+    let sema = Semaphore::new(4, 42);
+    let barrier = Arc::new(Barrier::new(4));
+    let racers = (0..4).map(|_| {
+        let sema = sema.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            let resource = sema.shutdown().wait();
+            assert!(sema.shutdown_initiated());
+            resource
+        })
+    }).collect::<Vec<_>>();
+    let resources = racers.into_iter().filter_map(|racer| racer.join().unwrap()).collect::<Vec<_>>();
+    assert_eq!(vec![42], resources);
+
+    let closed = Semaphore::new(1, ());
+    closed.close();
+    assert!(!closed.shutdown_initiated());
+}
+
+#[test]
+fn adaptive_access_only_blocks_at_threshold() {
+    use std::time::Instant;
+
+    let sema = Semaphore::new(4, ());
+    let mut guards = (0..3).map(|_| {
+        sema.try_access().expect("guard acquisition failed")
+    }).collect::<Vec<_>>();
+    assert_eq!(0.75, sema.utilization());
+    guards.push(sema.access_adaptive_block(0.8, Duration::from_secs(10)).expect("guard acquisition failed"));
+    assert_eq!(1.0, sema.utilization());
+
+    let start = Instant::now();
+    assert_eq!(AcquireError::Timeout,
+        sema.access_adaptive_block(0.8, Duration::from_millis(20)).err().unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    guards.pop();
+    assert!(sema.access_adaptive_block(0.0, Duration::from_millis(20)).is_ok());
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn wait_stats_record_only_blocked_acquisitions() {
+    let sema = Semaphore::new(1, ());
+    drop(sema.access().expect("guard acquisition failed"));
+    assert_eq!(0, sema.wait_stats().count);
+
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        drop(guard);
+    });
+    drop(sema.access().expect("guard acquisition failed"));
+    releaser.join().unwrap();
+
+    let stats = sema.wait_stats();
+    assert_eq!(1, stats.count);
+    assert!(stats.max >= Duration::from_millis(10));
+    assert_eq!(stats.total, stats.max);
+    assert_eq!(Some(stats.total), stats.mean());
+}
+
+#[test]
+fn resource_is_available_without_capacity() {
+    let sema = Semaphore::new(1, 42);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(Some(42), sema.resource().map(|resource| *resource));
+    drop(guard);
+    sema.shutdown();
+    assert_eq!(None, sema.resource());
+}
+
+#[test]
+fn resource_is_withheld_while_mutably_projected() {
+    let sema = Semaphore::new(1, (1, 2));
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let first = guard.map_mut(|pair| &mut pair.0).ok().expect("projection failed");
+    assert!(sema.resource().is_none());
+    drop(first);
+    let resource = sema.resource().expect("resource missing");
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert!(guard.map_mut(|pair| &mut pair.1).is_err());
+    drop(resource);
+}
+
+#[test]
+fn try_into_owned_requires_sole_handle() {
+    let sema = Semaphore::new(1, 1);
+    let clone = sema.clone();
+    let sema = sema.try_into_owned().err().expect("succeeded despite clone");
+    drop(clone);
+
+    let mut owned = sema.try_into_owned().ok().expect("conversion failed");
+    let guard = owned.try_access().expect("guard acquisition failed");
+    drop(guard);
+    *owned.get_mut().expect("resource not exclusive") += 1;
+    assert_eq!(2, *owned.into_shared().try_access().expect("guard acquisition failed"));
+}
+
+#[test]
+fn try_into_owned_fails_while_weak_handle_exists() {
+    let sema = Semaphore::new(1, 1);
+    let weak = sema.downgrade();
+    let sema = sema.try_into_owned().err().expect("succeeded despite weak handle");
+    let upgraded = weak.upgrade().expect("upgrade failed");
+    assert!(upgraded.try_into_owned().is_err());
+    drop(weak);
+
+    let mut owned = sema.try_into_owned().ok().expect("conversion failed");
+    assert!(owned.get_mut().is_some());
+}
+
+#[test]
+fn owned_get_mut_fails_while_guard_is_held() {
+    let mut owned = Semaphore::new(2, 1).try_into_owned().ok().expect("conversion failed");
+    let guard = owned.try_access().expect("guard acquisition failed");
+    assert!(owned.get_mut().is_none());
+    drop(guard);
+    assert!(owned.get_mut().is_some());
+}
+
+#[test]
+fn renewed_lease_is_not_reaped() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.lease(Duration::from_millis(50)).expect("guard acquisition failed");
+    for _ in 0..4 {
+        thread::sleep(Duration::from_millis(20));
+        assert!(guard.renew());
+        assert_eq!(0, sema.reap_expired());
+    }
+    assert!(!guard.is_expired());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+}
+
+#[test]
+fn expired_lease_is_reaped_once() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.lease(Duration::from_millis(10)).expect("guard acquisition failed");
+    thread::sleep(Duration::from_millis(20));
+    assert!(guard.is_expired());
+    assert_eq!(1, sema.reap_expired());
+    assert_eq!(0, sema.reap_expired());
+    assert!(!guard.renew());
+
+    let other = sema.try_access().expect("guard acquisition failed");
+    drop(guard);
+    assert_eq!(1, sema.active_count());
+    drop(other);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn from_value_has_capacity_one() {
+    let sema: Semaphore<u32> = 42.into();
+    assert_eq!(1, sema.capacity());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(42, *guard);
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+}
+
+#[test]
+fn explicit_release_restores_capacity() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    guard.release();
+    assert_eq!(0, sema.active_count());
+    assert!(sema.try_access().is_ok());
+}
+
+#[cfg(feature = "leak-detection")]
+#[test]
+#[should_panic(expected = "after all handles")]
+fn guard_outliving_semaphore_is_detected() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    drop(sema);
+    drop(guard);
+}
+
+#[cfg(feature = "leak-detection")]
+#[test]
+fn guard_outliving_semaphore_still_releases_access() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let sema = Semaphore::new(2, ());
+    let raw = sema.inner.raw.clone();
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let permit = sema.try_acquire_permit().expect("permit acquisition failed");
+    drop(sema);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(guard))).is_err());
+    assert_eq!(1, raw.active());
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(permit))).is_err());
+    assert!(!raw.is_active());
+}
+
+#[test]
+fn partial_release_unblocks_waiter() {
+    let sema = Semaphore::new(3, ());
+    let mut guard = sema.try_access_n(3).expect("guard acquisition failed");
+
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            let guard = sema.access().expect("guard acquisition failed");
+            guard.permits()
+        })
+    };
+    while sema.inner.raw.queued_count() == 0 {
+        thread::yield_now();
+    }
+    assert_eq!(1, guard.release_some(1));
+    assert_eq!(1, waiter.join().unwrap());
+    assert_eq!(2, guard.permits());
+    assert_eq!(2, sema.active_count());
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn panic_while_holding_guard_poisons() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let sema = Semaphore::with_poisoning(2, ());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = sema.try_access().expect("guard acquisition failed");
+        panic!("poisoning the semaphore");
+    }));
+    assert!(result.is_err());
+    assert!(sema.is_poisoned());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::Poisoned);
+    assert_eq!(sema.access().err().unwrap(), AcquireError::Poisoned);
+
+    sema.clear_poison();
+    assert!(sema.try_access().is_ok());
+}
+
+#[test]
+fn panic_without_poisoning_mode_is_ignored() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let sema = Semaphore::new(1, ());
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _guard = sema.try_access().expect("guard acquisition failed");
+        panic!("not poisoning the semaphore");
+    }));
+    assert!(!sema.is_poisoned());
+    assert!(sema.try_access().is_ok());
+}
+
+#[test]
+fn guards_can_be_shared_within_scoped_tasks() {
+    fn assert_send_sync<S: Send + Sync>(_: &S) {}
+
+    let sema = Semaphore::new(2, vec![1, 2, 3]);
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let guard = sema.access().expect("guard acquisition failed");
+                assert_send_sync(&guard);
+                assert!(sema.active_count() <= 2);
+                // A guard borrowed by nested tasks keeps holding its access for all of them.
+                thread::scope(|inner| {
+                    inner.spawn(|| assert_eq!(6, guard.iter().sum::<i32>()));
+                    inner.spawn(|| assert_eq!(3, guard.len()));
+                });
+            });
+        }
+    });
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn transfer_moves_access_between_semaphores() {
+    let first = Semaphore::new(1, 1);
+    let second = Semaphore::new(1, 2);
+
+    let guard = first.try_access().expect("guard acquisition failed");
+    let guard = guard.transfer_to(&second).ok().expect("transfer failed");
+    assert_eq!(2, *guard);
+    assert_eq!(0, first.active_count());
+    assert_eq!(1, second.active_count());
+
+    let other = first.try_access().expect("guard acquisition failed");
+    let (other, err) = other.transfer_to(&second).err().expect("transfer succeeded despite full capacity");
+    assert_eq!(err, TryAccessError::NoCapacity);
+    assert_eq!(1, *other);
+    assert_eq!(1, first.active_count());
+    drop(guard);
+}
+
+#[test]
+fn shutdown_reports_progress_while_draining() {
+    let sema = Semaphore::new(3, 42);
+    let guards = (0..3).map(|_| sema.try_access().expect("guard acquisition failed")).collect::<Vec<_>>();
+    let releaser = thread::spawn(move || {
+        for guard in guards {
+            thread::sleep(Duration::from_millis(30));
+            drop(guard);
+        }
+    });
+
+    let mut reports = Vec::new();
+    let resource = sema.shutdown().wait_with_progress(Duration::from_millis(5), |active| reports.push(active));
+    releaser.join().unwrap();
+    assert_eq!(Some(42), resource);
+    assert!(reports.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert_eq!(Some(&3), reports.first());
+    assert!(reports.contains(&1));
+}
+
+#[test]
+fn cancelling_token_interrupts_blocked_access() {
+    use super::cancel::CancelFlag;
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let token = CancelFlag::new();
+
+    let waiter = {
+        let sema = sema.clone();
+        let token = token.clone();
+        thread::spawn(move || sema.access_cancellable(&token).err())
+    };
+    while sema.inner.raw.queued_count() == 0 {
+        thread::yield_now();
+    }
+    token.cancel();
+    assert_eq!(Some(AcquireError::Cancelled), waiter.join().unwrap());
+    assert_eq!(0, sema.inner.raw.queued_count());
+
+    drop(guard);
+    assert_eq!(sema.access_cancellable(&token).err().unwrap(), AcquireError::Cancelled);
+    assert!(sema.access_cancellable(&CancelFlag::new()).is_ok());
+}
+
+#[test]
+fn nonzero_capacity_constructor() {
+    use std::num::NonZeroUsize;
+
+    let sema = Semaphore::with_nonzero_capacity(NonZeroUsize::new(2).unwrap(), ());
+    assert_eq!(2, sema.capacity());
+    let _first = sema.try_access().expect("guard acquisition failed");
+    let _second = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+}
+
+#[test]
+fn cloning_guard_acquires_another_access() {
+    let sema = Semaphore::new(2, ());
+    let guard = sema.try_access_tagged("job").expect("guard acquisition failed");
+    let clone = guard.clone();
+    assert_eq!(2, sema.active_count());
+    assert_eq!(Some("job"), clone.tag());
+    assert_eq!(guard.try_clone().err().unwrap(), TryAccessError::NoCapacity);
+    drop(guard);
+    drop(clone);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn adding_permit_wakes_single_acquirer() {
+    use std::sync::mpsc;
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let (sender, receiver) = mpsc::channel();
+    let waiters = (0..8).map(|_| {
+        let sema = sema.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            if let Ok(guard) = sema.access() {
+                sender.send(guard).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+    while sema.inner.raw.queued_count() < 8 {
+        thread::yield_now();
+    }
+
+    sema.add_permits(1);
+    assert_eq!(2, sema.capacity());
+    let acquired = receiver.recv_timeout(Duration::from_secs(5)).expect("no acquirer was woken");
+    assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    assert_eq!(7, sema.inner.raw.queued_count());
+    assert_eq!(2, sema.active_count());
+
+    drop((guard, acquired));
+    sema.shutdown();
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn adding_permits_is_deferred_while_projected() {
+    let sema = Semaphore::new(1, 0);
+    let mut mapped = sema.try_access().expect("guard acquisition failed")
+        .map_mut(|value| value).ok().expect("projection failed");
+    sema.add_permits(1);
+    assert_eq!(1, sema.capacity());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    *mapped += 1;
+    drop(mapped);
+    assert_eq!(2, sema.capacity());
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_round_trip() {
+    use super::ffi::{semaphore_free, semaphore_new, semaphore_release, semaphore_try_acquire};
+
+    unsafe {
+        let sema = semaphore_new(1);
+        let guard = semaphore_try_acquire(sema);
+        assert!(!guard.is_null());
+        assert!(semaphore_try_acquire(sema).is_null());
+        semaphore_release(guard);
+
+        let guard = semaphore_try_acquire(sema);
+        assert!(!guard.is_null());
+        semaphore_release(guard);
+        semaphore_free(sema);
+        assert!(semaphore_try_acquire(std::ptr::null()).is_null());
+    }
+}
+
+#[test]
+fn shrinking_capacity_blocks_acquisition_while_guards_are_held() {
+    let sema = Semaphore::new(3, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+
+    sema.remove_permits(2);
+    assert_eq!(1, sema.capacity());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(first);
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(second);
+    let _third = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+
+    sema.remove_permits(5);
+    assert_eq!(0, sema.capacity());
+}
+
+#[test]
+fn snapshot_agrees_with_live_state() {
+    let sema = Semaphore::new(3, ());
+    let guard = sema.try_access_n(2).expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_n_timeout(2, Duration::from_secs(5)).is_ok())
+    };
+    while sema.inner.raw.queued_count() == 0 {
+        thread::yield_now();
+    }
+
+    let snapshot = sema.snapshot();
+    assert_eq!(3, snapshot.capacity);
+    assert_eq!(2, snapshot.active);
+    assert_eq!(1, snapshot.available);
+    assert_eq!(1, snapshot.waiters);
+    assert!(!snapshot.is_shutdown);
+
+    drop(guard);
+    assert!(waiter.join().unwrap());
+    sema.close();
+    let snapshot = sema.snapshot();
+    assert_eq!((0, 3, 0), (snapshot.active, snapshot.available, snapshot.waiters));
+    assert!(snapshot.is_shutdown);
+}
+
+#[test]
+fn higher_priority_waiter_is_signalled_to_holder() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.access_with_priority(1).expect("guard acquisition failed");
+    assert_eq!(1, guard.priority());
+    assert!(!guard.has_higher_priority_waiter());
+
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_with_priority(5).map(|guard| guard.priority()))
+    };
+    while !guard.has_higher_priority_waiter() {
+        thread::yield_now();
+    }
+    drop(guard);
+    assert_eq!(Ok(5), waiter.join().unwrap());
+
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert!(!guard.has_higher_priority_waiter());
+}
+
+#[test]
+fn guards_are_unpin_regardless_of_resource() {
+    use std::marker::PhantomPinned;
+
+    fn assert_unpin<U: Unpin>() {}
+    assert_unpin::<super::SemaphoreGuard<PhantomPinned>>();
+    assert_unpin::<super::SemaphoreGuard<dyn Fn() + Send + Sync>>();
+    assert_unpin::<super::PermitGuard>();
+    #[cfg(feature = "async")]
+    {
+        assert_unpin::<super::AcquireFuture<PhantomPinned>>();
+        assert_unpin::<super::PermitStream<PhantomPinned>>();
+    }
+}
+
+#[test]
+fn graded_access_crosses_soft_and_hard_limit() {
+    let sema = Semaphore::with_soft_limit(2, 3, ());
+    assert_eq!(3, sema.capacity());
+    let (_first, state) = sema.try_access_graded().expect("guard acquisition failed");
+    assert_eq!(LimitState::Normal, state);
+    let (second, state) = sema.try_access_graded().expect("guard acquisition failed");
+    assert_eq!(LimitState::Normal, state);
+    let (_third, state) = sema.try_access_graded().expect("guard acquisition failed");
+    assert_eq!(LimitState::OverSoft, state);
+    assert_eq!(sema.try_access_graded().err().unwrap(), TryAccessError::NoCapacity);
+
+    drop(second);
+    let (_second, state) = sema.try_access_graded().expect("guard acquisition failed");
+    assert_eq!(LimitState::OverSoft, state);
+}
+
+#[test]
+fn graded_access_without_soft_limit_is_normal() {
+    let sema = Semaphore::new(1, ());
+    let (_guard, state) = sema.try_access_graded().expect("guard acquisition failed");
+    assert_eq!(LimitState::Normal, state);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn single_async_waiter_is_woken_without_notification() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let sema = Semaphore::new(2, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let mut lone = sema.acquire_async();
+    assert!(Pin::new(&mut lone).poll(&mut cx).is_pending());
+    assert_eq!(0, sema.inner.raw.waiter_count());
+    assert_eq!(1, sema.snapshot().waiters);
+
+    let notifications = sema.inner.raw.notification_count();
+    drop(first);
+    assert_eq!(1, wakes.0.load(Ordering::SeqCst));
+    assert_eq!(notifications, sema.inner.raw.notification_count());
+    let guard = match Pin::new(&mut lone).poll(&mut cx) {
+        Poll::Ready(result) => result.expect("guard acquisition failed"),
+        Poll::Pending => panic!("future still pending after release")
+    };
+
+    // With the slot taken, further waiters fall back to the shared registry.
+    let mut other = sema.acquire_async();
+    let mut another = sema.acquire_async();
+    assert!(Pin::new(&mut other).poll(&mut cx).is_pending());
+    assert!(Pin::new(&mut another).poll(&mut cx).is_pending());
+    assert_eq!(1, sema.inner.raw.waiter_count());
+    assert_eq!(2, sema.inner.raw.registered_wakers());
+    drop(guard);
+    assert_eq!(3, wakes.0.load(Ordering::SeqCst));
+    drop((other, another, second));
+}
+
+#[test]
+fn forgotten_clone_does_not_affect_accounting() {
+    use std::mem;
+
+    let sema = Semaphore::new(2, 42);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    mem::forget(sema.clone());
+
+    let other = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(other);
+    assert_eq!(1, sema.active_count());
+
+    let handle = sema.shutdown();
+    let releaser = thread::spawn(move || drop(guard));
+    assert_eq!(Some(42), handle.wait());
+    releaser.join().unwrap();
+    assert!(sema.try_into_owned().is_err());
+}
+
+#[test]
+fn barrier_access_releases_full_group_together() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let sema = Semaphore::new(3, ());
+    let passed = Arc::new(AtomicUsize::new(0));
+    let spawn_member = || {
+        let sema = sema.clone();
+        let passed = passed.clone();
+        thread::spawn(move || {
+            let guard = sema.barrier_access(3).expect("guard acquisition failed");
+            passed.fetch_add(1, Ordering::SeqCst);
+            drop(guard);
+        })
+    };
+    let mut members = vec![spawn_member(), spawn_member()];
+    while sema.active_count() < 2 {
+        thread::yield_now();
+    }
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(0, passed.load(Ordering::SeqCst));
+
+    members.push(spawn_member());
+    for member in members {
+        member.join().unwrap();
+    }
+    assert_eq!(3, passed.load(Ordering::SeqCst));
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn barrier_access_times_out_without_full_group() {
+    let sema = Semaphore::new(2, ());
+    assert_eq!(sema.barrier_access(3).err().unwrap(), AcquireError::NoCapacity);
+    assert_eq!(sema.barrier_access_timeout(2, Duration::from_millis(10)).err().unwrap(),
+        AcquireError::Timeout);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn guard_can_be_wrapped_on_acquisition() {
+    use std::rc::Rc;
+
+    let sema = Semaphore::new(1, 42);
+    let shared = sema.try_access_with_guard(Rc::new).expect("guard acquisition failed");
+    let copy = shared.clone();
+    assert_eq!(42, **copy);
+    assert_eq!(sema.try_access_with_guard(Rc::new).err().unwrap(), TryAccessError::NoCapacity);
+    drop(shared);
+    assert_eq!(1, sema.active_count());
+    drop(copy);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn exactly_one_concurrent_shutdown_wins_resource() {
+    let sema = Semaphore::new(1, 42);
+    let handles = (0..8).map(|_| {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            let handle = sema.shutdown();
+            let won = handle.won_resource();
+            assert_eq!(won, handle.wait().is_some());
+            won
+        })
+    }).collect::<Vec<_>>();
+    let winners = handles.into_iter()
+        .map(|handle| handle.join().unwrap())
+        .filter(|&won| won)
+        .count();
+    assert_eq!(1, winners);
+    assert!(!sema.shutdown().won_resource());
+}
+
+#[test]
+fn committed_reservation_releases_once() {
+    let sema = Semaphore::new(1, 42);
+    let reservation = sema.reserve().expect("reservation failed");
+    assert_eq!(1, sema.active_count());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    assert_eq!(sema.reserve().err().unwrap(), TryAccessError::NoCapacity);
+    let guard = reservation.commit();
+    assert_eq!(42, *guard);
+    assert_eq!(1, sema.active_count());
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn cancelled_reservation_releases_once() {
+    let sema = Semaphore::new(2, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    sema.reserve().expect("reservation failed").cancel();
+    assert_eq!(1, sema.active_count());
+    drop(sema.reserve().expect("reservation failed"));
+    assert_eq!(1, sema.active_count());
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn observer_records_lifecycle_events() {
+    use parking_lot::Mutex;
+    use super::SemaphoreObserver;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Acquire(usize),
+        Release(usize),
+        Denied(TryAccessError),
+        Shutdown
+    }
+
+    struct Recorder(Arc<Mutex<Vec<Event>>>);
+
+    impl SemaphoreObserver for Recorder {
+        fn on_acquire(&self, active: usize) {
+            self.0.lock().push(Event::Acquire(active));
+        }
+
+        fn on_release(&self, active: usize) {
+            self.0.lock().push(Event::Release(active));
+        }
+
+        fn on_denied(&self, reason: TryAccessError) {
+            self.0.lock().push(Event::Denied(reason));
+        }
+
+        fn on_shutdown(&self) {
+            self.0.lock().push(Event::Shutdown);
+        }
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sema = Semaphore::with_observer(2, (), Recorder(events.clone()));
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(first);
+    drop(second);
+    assert!(sema.shutdown().wait().is_some());
+    sema.close();
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::Shutdown);
+    assert_eq!(*events.lock(), vec![
+        Event::Acquire(1),
+        Event::Acquire(2),
+        Event::Denied(TryAccessError::NoCapacity),
+        Event::Release(1),
+        Event::Release(0),
+        Event::Shutdown,
+        Event::Denied(TryAccessError::Shutdown)
+    ]);
+}
+
+/// Let `acquirers` threads compete for fair access until `grants` accesses were granted,
+/// and count the grants of each acquirer from the fairness trace.
+#[cfg(feature = "fairness-trace")]
+fn fair_grant_counts(capacity: usize, acquirers: usize, grants: usize) -> Vec<usize> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let sema = Semaphore::new(capacity, ());
+    let stop = Arc::new(AtomicBool::new(false));
+    // Hold all capacity until every acquirer is queued, so that none gets a head start.
+    let gate = sema.try_access_n(capacity).expect("guard acquisition failed");
+    let handles = (0..acquirers).map(|acquirer| {
+        let sema = sema.clone();
+        let stop = stop.clone();
+        thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                let guard = sema.access_fair_traced(acquirer).expect("guard acquisition failed");
+                thread::sleep(Duration::from_millis(1));
+                drop(guard);
+            }
+        })
+    }).collect::<Vec<_>>();
+    while sema.snapshot().waiters < acquirers {
+        thread::yield_now();
+    }
+    drop(gate);
+    while sema.fairness_trace().len() < grants {
+        thread::sleep(Duration::from_millis(1));
+    }
+    stop.store(true, Ordering::SeqCst);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let mut counts = vec![0; acquirers];
+    for &acquirer in &sema.fairness_trace()[..grants] {
+        counts[acquirer] += 1;
+    }
+    counts
+}
+
+#[test]
+#[cfg(feature = "fairness-trace")]
+fn fair_access_grants_exclusive_access_evenly() {
+    let counts = fair_grant_counts(1, 4, 40);
+    assert!(counts.iter().max().unwrap() - counts.iter().min().unwrap() <= 1, "{:?}", counts);
+}
+
+#[test]
+#[cfg(feature = "fairness-trace")]
+fn fair_access_grants_shared_access_evenly() {
+    let counts = fair_grant_counts(2, 5, 40);
+    assert!(counts.iter().max().unwrap() - counts.iter().min().unwrap() <= 2, "{:?}", counts);
+}
+
+#[test]
+fn gate_admits_exactly_opened_capacity() {
+    use std::sync::mpsc;
+
+    let sema = Semaphore::new(1, ());
+    sema.close_gate();
+    let (tx, rx) = mpsc::channel();
+    let handles = (0..3).map(|_| {
+        let sema = sema.clone();
+        let tx = tx.clone();
+        thread::spawn(move || {
+            tx.send(sema.access().expect("guard acquisition failed")).unwrap();
+        })
+    }).collect::<Vec<_>>();
+    while sema.snapshot().waiters < 3 {
+        thread::yield_now();
+    }
+    assert!(rx.recv_timeout(Duration::from_millis(20)).is_err());
+    sema.open(2);
+    let first = rx.recv().unwrap();
+    let second = rx.recv().unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(20)).is_err());
+    assert_eq!(2, sema.active_count());
+    // Closing the gate leaves the held guards alone.
+    sema.close_gate();
+    assert_eq!(0, sema.capacity());
+    drop(first);
+    drop(second);
+    assert!(rx.recv_timeout(Duration::from_millis(20)).is_err());
+    sema.open(1);
+    drop(rx.recv().unwrap());
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn last_guard_reports_saturation() {
+    let sema = Semaphore::new(3, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    let third = sema.try_access().expect("guard acquisition failed");
+    assert!(!first.saturated_on_acquire());
+    assert!(!second.saturated_on_acquire());
+    assert!(third.saturated_on_acquire());
+    drop(third);
+    let again = sema.try_access_n(1).expect("guard acquisition failed");
+    assert!(again.saturated_on_acquire());
+    drop(first);
+    // Blocking acquisitions do not record saturation.
+    assert!(!sema.access().expect("guard acquisition failed").saturated_on_acquire());
+}
+
+#[test]
+fn local_semaphore_limits_access() {
+    let sema = LocalSemaphore::new(2, 42);
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.clone().try_access().expect("guard acquisition failed");
+    assert_eq!(42, *second);
+    assert_eq!(2, sema.active_count());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(first);
+    assert_eq!(1, sema.active_count());
+    drop(second);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn released_access_is_handed_to_fair_waiter() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            let guard = sema.access_fair().expect("guard acquisition failed");
+            thread::sleep(Duration::from_millis(10));
+            drop(guard);
+        })
+    };
+    while sema.snapshot().waiters < 1 {
+        thread::yield_now();
+    }
+    drop(guard);
+    // The access went straight to the fair waiter, even if it has not woken up yet.
+    assert_eq!(1, sema.active_count());
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    waiter.join().unwrap();
+    assert_eq!(0, sema.active_count());
+    sema.try_access().expect("guard acquisition failed");
+}
+
+#[test]
+fn registry_prunes_dropped_semaphores() {
+    use super::SemaphoreRegistry;
+
+    let registry = SemaphoreRegistry::new();
+    let database = Semaphore::new(2, ());
+    let cache = Semaphore::new(3, ());
+    let search = Semaphore::new(4, ());
+    registry.register("database", &database);
+    registry.register("cache", &cache);
+    registry.register("search", &search);
+    let _guard = database.try_access().expect("guard acquisition failed");
+    drop(cache);
+    let snapshots = registry.snapshot_all();
+    let names = snapshots.iter().map(|entry| entry.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(vec!["database", "search"], names);
+    assert_eq!(1, snapshots[0].1.active);
+    assert_eq!(4, snapshots[1].1.capacity);
+    // Only the semaphore itself and its guard count, not the registry.
+    assert_eq!(2, database.handle_count());
+    drop(search);
+    assert_eq!(1, registry.snapshot_all().len());
+}
+
+#[test]
+#[cfg(all(feature = "debug-deadlock", debug_assertions))]
+fn long_blocking_wait_reports_deadlock() {
+    let sema = Semaphore::new(1, ());
+    sema.set_deadlock_threshold(Duration::from_millis(10));
+    let leaked = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(sema.access_timeout(Duration::from_millis(50)).err().unwrap(), AcquireError::Timeout);
+    assert_eq!(1, sema.inner.raw.deadlock_report_count());
+    // Waits that end before the threshold are not reported.
+    assert_eq!(sema.access_timeout(Duration::from_millis(5)).err().unwrap(), AcquireError::Timeout);
+    assert_eq!(1, sema.inner.raw.deadlock_report_count());
+    drop(leaked);
+}
+
+#[test]
+fn guards_pop_from_heap_in_acquisition_order() {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let sema = Semaphore::new(3, ());
+    let guards = (0..3).map(|_| sema.try_access().expect("guard acquisition failed")).collect::<Vec<_>>();
+    let sequences = guards.iter().map(|guard| guard.sequence()).collect::<Vec<_>>();
+    let mut oldest_first = guards.into_iter().map(Reverse).collect::<BinaryHeap<_>>();
+    let first = oldest_first.pop().unwrap().0;
+    assert_eq!(sequences[0], first.sequence());
+    let mut newest_first = oldest_first.into_iter().map(|guard| guard.0).collect::<BinaryHeap<_>>();
+    assert_eq!(sequences[2], newest_first.pop().unwrap().sequence());
+    assert_eq!(sequences[1], newest_first.pop().unwrap().sequence());
+    assert_eq!(1, sema.active_count());
+}
+
+#[test]
+fn slow_release_is_reported() {
+    let sema = Semaphore::with_hold_threshold(1, (), Duration::from_millis(20));
+    drop(sema.try_access().expect("guard acquisition failed"));
+    assert_eq!(0, sema.inner.raw.long_hold_count());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    thread::sleep(Duration::from_millis(30));
+    drop(guard);
+    assert_eq!(1, sema.inner.raw.long_hold_count());
+}
+
+#[test]
+fn budget_is_shared_among_tenants_by_weight() {
+    use super::BudgetSemaphore;
+
+    let budget = BudgetSemaphore::new(8);
+    let first = budget.add_tenant(1, ());
+    assert_eq!(8, first.limit());
+    let guards = (0..5).map(|_| first.as_semaphore().try_access().expect("guard acquisition failed")).collect::<Vec<_>>();
+    let second = budget.add_tenant(1, ());
+    assert_eq!(4, first.limit());
+    assert_eq!(4, second.limit());
+    // Access held over the new limit stays valid, but no more is granted.
+    assert_eq!(first.as_semaphore().try_access().err().unwrap(), TryAccessError::NoCapacity);
+    drop(guards);
+    let third = budget.add_tenant(2, ());
+    assert_eq!((2, 2, 4), (first.limit(), second.limit(), third.limit()));
+    drop(second);
+    drop(third);
+    assert_eq!(8, first.limit());
+}
+
+#[test]
+fn elastic_access_returns_to_base_capacity() {
+    let sema = Semaphore::new(2, ());
+    let guards = (0..4).map(|_| sema.try_access_elastic(4).expect("guard acquisition failed")).collect::<Vec<_>>();
+    assert_eq!(4, sema.capacity());
+    assert_eq!(sema.try_access_elastic(4).err().unwrap(), TryAccessError::NoCapacity);
+    drop(guards);
+    assert_eq!(2, sema.capacity());
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn idle_resource_can_be_taken() {
+    let sema = Semaphore::new(2, 42);
+    assert_eq!(Ok(42), sema.try_take_if_idle());
+    assert!(sema.snapshot().is_shutdown);
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::Shutdown);
+    assert_eq!(Err(TryTakeError::Shutdown), sema.try_take_if_idle());
+}
+
+#[test]
+fn resource_in_use_is_not_taken() {
+    let sema = Semaphore::new(2, 42);
+    let guard = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(Err(TryTakeError::InUse), sema.try_take_if_idle());
+    drop(guard);
+    let clone = sema.clone();
+    assert_eq!(Err(TryTakeError::Shared), sema.try_take_if_idle());
+    drop(clone);
+    assert!(!sema.snapshot().is_shutdown);
+    assert_eq!(Ok(42), sema.try_take_if_idle());
+}
+
+#[test]
+fn guard_acquires_additional_accesses() {
+    let sema = Semaphore::new(4, ());
+    let mut guard = sema.try_access().expect("guard acquisition failed");
+    guard.acquire_additional(2).expect("additional acquisition failed");
+    assert_eq!(3, guard.permits());
+    assert_eq!(3, sema.active_count());
+    assert_eq!(guard.acquire_additional(2).err().unwrap(), TryAccessError::NoCapacity);
+    assert_eq!(3, guard.permits());
+    assert_eq!(3, sema.active_count());
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn compat_semaphore_counts_like_legacy_api() {
+    use super::compat;
+
+    let sem = Arc::new(compat::Semaphore::new(1));
+    sem.acquire();
+    sem.release();
+    {
+        let _guard = sem.access();
+    }
+    // Releasing without acquiring first raises the count.
+    sem.release();
+    sem.acquire();
+    sem.acquire();
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || drop(sem.access()))
+    };
+    thread::sleep(Duration::from_millis(10));
+    assert!(!waiter.is_finished());
+    sem.release();
+    waiter.join().unwrap();
+}
+
+#[test]
+fn compat_semaphore_signals_across_threads() {
+    use super::compat;
+
+    let sem = Arc::new(compat::Semaphore::new(-1));
+    let waiter = {
+        let sem = sem.clone();
+        thread::spawn(move || sem.acquire())
+    };
+    sem.release();
+    thread::sleep(Duration::from_millis(10));
+    assert!(!waiter.is_finished());
+    sem.release();
+    waiter.join().unwrap();
+}
+
+#[test]
+fn wait_ticket_position_decreases_as_earlier_waiters_are_served() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let first = sema.enqueue();
+    let second = sema.enqueue();
+    let third = sema.enqueue();
+    assert_eq!((0, 1, 2), (first.position(), second.position(), third.position()));
+    // The released access is handed to the first ticket, which keeps its place until it waits.
+    drop(guard);
+    assert_eq!(sema.try_access().err().unwrap(), TryAccessError::NoCapacity);
+    let guard = first.wait().expect("guard acquisition failed");
+    assert_eq!((0, 1), (second.position(), third.position()));
+    drop(second);
+    assert_eq!(0, third.position());
+    drop(guard);
+    drop(third.wait().expect("guard acquisition failed"));
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn typed_guard_releases_its_accesses() {
+    let sema = Semaphore::new(3, 42);
+    let pair = sema.try_access_typed::<2>().expect("guard acquisition failed");
+    assert_eq!(42, *pair);
+    assert_eq!(2, sema.active_count());
+    assert_eq!(sema.try_access_typed::<2>().err().unwrap(), TryAccessError::NoCapacity);
+    let single = sema.try_access_typed::<1>().expect("guard acquisition failed");
+    drop(pair);
+    assert_eq!(1, sema.active_count());
+    assert_eq!(1, single.into_guard().permits());
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn wait_for_available_wakes_once_enough_is_released() {
+    let sema = Semaphore::new(3, ());
+    let mut guards = (0..3).map(|_| sema.try_access().expect("guard acquisition failed")).collect::<Vec<_>>();
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.wait_for_available(2))
+    };
+    while sema.snapshot().waiters < 1 {
+        thread::yield_now();
+    }
+    guards.pop();
+    thread::sleep(Duration::from_millis(10));
+    assert!(!waiter.is_finished());
+    guards.pop();
+    waiter.join().unwrap();
+    assert_eq!(1, sema.active_count());
+}
+
+#[test]
+fn wait_for_available_wakes_once_capacity_has_grown() {
+    let sema = Semaphore::new(2, ());
+    let _guard = sema.try_access().expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.wait_for_available(3))
+    };
+    while sema.snapshot().waiters < 1 {
+        thread::yield_now();
+    }
+    sema.add_permits(1);
+    thread::sleep(Duration::from_millis(10));
+    assert!(!waiter.is_finished());
+    sema.add_permits(1);
+    waiter.join().unwrap();
+    assert_eq!(1, sema.active_count());
+}
+
+#[test]
+fn adaptive_limit_follows_reported_outcomes() {
+    use super::AdaptiveSemaphore;
+
+    let sema = AdaptiveSemaphore::new(2, 10, Duration::from_millis(100), ());
+    assert_eq!(2, sema.limit());
+    for _ in 0..6 {
+        sema.try_access().expect("guard acquisition failed").record_success(Duration::from_millis(10));
+    }
+    assert_eq!(8, sema.limit());
+    assert_eq!(8, sema.as_semaphore().capacity());
+    sema.try_access().expect("guard acquisition failed").record_failure();
+    assert_eq!(4, sema.limit());
+    sema.try_access().expect("guard acquisition failed").record_success(Duration::from_secs(1));
+    assert_eq!(2, sema.limit());
+    // The limit never drops below the minimum.
+    sema.try_access().expect("guard acquisition failed").record_failure();
+    assert_eq!(2, sema.limit());
+    assert_eq!(0, sema.as_semaphore().active_count());
+}
+
+#[test]
+fn adaptive_limit_never_drops_to_zero() {
+    use super::AdaptiveSemaphore;
+
+    let sema = AdaptiveSemaphore::new(0, 4, Duration::from_millis(100), ());
+    assert_eq!(1, sema.limit());
+    sema.access().expect("guard acquisition failed").record_failure();
+    assert_eq!(1, sema.limit());
+    sema.access().expect("guard acquisition failed").record_success(Duration::from_millis(10));
+    assert_eq!(2, sema.limit());
+}
+
+#[test]
+fn same_key_requests_share_one_computation() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use super::SingleFlightSemaphore;
+
+    let sema = Arc::new(SingleFlightSemaphore::new(2));
+    let computations = Arc::new(AtomicUsize::new(0));
+    let (started_tx, started_rx) = mpsc::channel();
+    let (finish_tx, finish_rx) = mpsc::channel::<()>();
+    let leader = {
+        let sema = sema.clone();
+        let computations = computations.clone();
+        thread::spawn(move || sema.access("key", move || {
+            computations.fetch_add(1, Ordering::SeqCst);
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+            42
+        }).expect("computation failed"))
+    };
+    started_rx.recv().unwrap();
+    let follower = {
+        let sema = sema.clone();
+        let computations = computations.clone();
+        thread::spawn(move || sema.access("key", move || {
+            computations.fetch_add(1, Ordering::SeqCst);
+            0
+        }).expect("computation failed"))
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(1, sema.as_semaphore().active_count());
+    assert_eq!(1, sema.in_flight());
+    finish_tx.send(()).unwrap();
+    let (first, second) = (leader.join().unwrap(), follower.join().unwrap());
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(42, *first);
+    assert_eq!(1, computations.load(Ordering::SeqCst));
+    assert_eq!(0, sema.in_flight());
+}
+
+#[test]
+#[cfg(feature = "throughput")]
+fn throughput_averages_events_over_window() {
+    use std::time::Instant;
+    use super::throughput::Throughput;
+    use super::THROUGHPUT_WINDOW_SECS;
+
+    let start = Instant::now();
+    let throughput = Throughput::new(start);
+    // One acquire in the first second, two in the second, and so on, with three releases each.
+    for second in 0..12 {
+        let at = start + Duration::from_millis(second * 1000 + 500);
+        for _ in 0..second + 1 {
+            throughput.record_acquire(at);
+        }
+        for _ in 0..3 {
+            throughput.record_release(at);
+        }
+    }
+    // Seconds 2 through 11 are within the window, holding 3 + ... + 12 acquires.
+    let now = start + Duration::from_millis(11_500);
+    assert_eq!(10, THROUGHPUT_WINDOW_SECS);
+    assert_eq!((7.5, 3.0), throughput.rates(now));
+    // Before the window is filled, the rates cover the time since the start.
+    assert_eq!((0.0, 0.0), Throughput::new(start).rates(start));
+    let young = Throughput::new(start);
+    young.record_acquire(start);
+    young.record_acquire(start + Duration::from_secs(1));
+    assert_eq!((1.0, 0.0), young.rates(start + Duration::from_secs(2)));
+}
+
+#[test]
+#[cfg(feature = "throughput")]
+fn recent_throughput_counts_accesses() {
+    let sema = Semaphore::new(1, ());
+    assert_eq!((0.0, 0.0), sema.recent_throughput());
+    for _ in 0..4 {
+        sema.try_access().expect("guard acquisition failed");
+    }
+    let (acquires, releases) = sema.recent_throughput();
+    assert!(acquires > 0.0);
+    assert_eq!(acquires, releases);
+}
+
+#[cfg(all(feature = "async", feature = "debug-holders"))]
+#[test]
+fn diagnose_stuck_reports_long_held_tagged_guard() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    let sema = Semaphore::new(2, ());
+    let mut cx = Context::from_waker(Waker::noop());
+    let line = line!() + 1;
+    let mut future = sema.acquire_async().tagged("request-7");
+    let stuck = match Pin::new(&mut future).poll(&mut cx) {
+        Poll::Ready(result) => result.expect("guard acquisition failed"),
+        Poll::Pending => panic!("future pending with capacity available")
+    };
+    assert_eq!(Some("request-7"), stuck.tag());
+    thread::sleep(Duration::from_millis(50));
+    let fresh = sema.try_access().expect("guard acquisition failed");
+    let report = sema.diagnose_stuck(Duration::from_millis(30));
+    assert_eq!(1, report.len());
+    assert_eq!(Some("request-7"), report[0].tag.as_deref());
+    assert_eq!(line, report[0].site.line());
+    assert!(report[0].held_for >= Duration::from_millis(50));
+    drop(stuck);
+    assert!(sema.diagnose_stuck(Duration::from_millis(30)).is_empty());
+    drop(fresh);
+}
+
+#[test]
+fn erased_guards_of_different_types_release_on_drop() {
+    let numbers = Semaphore::new(1, 42);
+    let names = Semaphore::new(1, String::from("name"));
+    let erased = vec![
+        numbers.try_access().expect("guard acquisition failed").into_erased(),
+        names.try_access().expect("guard acquisition failed").into_erased()
+    ];
+    assert_eq!(1, numbers.active_count());
+    assert_eq!(1, names.active_count());
+    drop(erased);
+    assert_eq!(0, numbers.active_count());
+    assert_eq!(0, names.active_count());
+
+    let erased = numbers.try_access().expect("guard acquisition failed").into_erased();
+    assert!(erased.is::<i32>());
+    let erased = erased.downcast::<String>().err().expect("downcast to the wrong type");
+    let guard = erased.downcast::<i32>().ok().expect("downcast failed");
+    assert_eq!(42, *guard);
+    assert_eq!(1, numbers.active_count());
+}
+
+#[test]
+fn poll_acquire_catches_release_before_registration() {
+    use std::task::{Context, Poll, Waker};
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    // The release lands after the first attempt failed, but before the task is registered,
+    // so no wakeup is ever delivered for it.
+    sema.inner.raw.set_before_register(move || drop(guard));
+    let mut cx = Context::from_waker(Waker::noop());
+    match sema.poll_acquire(&mut cx) {
+        Poll::Ready(result) => assert!(result.is_ok()),
+        Poll::Pending => panic!("release between attempt and registration was missed")
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn acquire_future_catches_release_before_registration() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    sema.inner.raw.set_before_register(move || drop(guard));
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut future = sema.acquire_async();
+    match Pin::new(&mut future).poll(&mut cx) {
+        Poll::Ready(result) => assert!(result.is_ok()),
+        Poll::Pending => panic!("release between attempt and registration was missed")
+    }
+    assert_eq!(0, sema.inner.raw.registered_wakers());
+}
+
+#[test]
+fn waiter_storage_shrinks_after_burst() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let waiters = (0..128).map(|_| {
+        let sema = sema.clone();
+        thread::spawn(move || drop(sema.access().expect("guard acquisition failed")))
+    }).collect::<Vec<_>>();
+    while sema.inner.raw.queued_count() < 128 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(sema.inner.raw.queue_storage_capacity() >= 128);
+    drop(guard);
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(0, sema.inner.raw.queued_count());
+    assert!(sema.inner.raw.queue_storage_capacity() <= 32);
+    sema.set_retained_waiters(0);
+    assert_eq!(0, sema.inner.raw.queue_storage_capacity());
+}
+
+#[test]
+fn add_permits_saturates_at_usize_max() {
+    let sema = Semaphore::new(usize::MAX - 1, ());
+    sema.add_permits(5);
+    assert_eq!(usize::MAX, sema.capacity());
+    sema.add_permits(usize::MAX);
+    assert_eq!(usize::MAX, sema.capacity());
+    let guard = sema.try_access_n(usize::MAX).expect("guard acquisition failed");
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn remove_permits_saturates_at_zero() {
+    let sema = Semaphore::new(usize::MAX, ());
+    sema.remove_permits(usize::MAX);
+    assert_eq!(0, sema.capacity());
+    sema.remove_permits(usize::MAX);
+    assert_eq!(0, sema.capacity());
+    sema.add_permits(1);
+    assert_eq!(1, sema.capacity());
+    sema.try_access().expect("guard acquisition failed");
+}
+
+#[test]
+fn parked_acquirer_fails_once_capacity_is_removed() {
+    let sema = Semaphore::new(2, ());
+    let _guards = sema.try_access_n(2).expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access().err())
+    };
+    while sema.snapshot().waiters < 1 {
+        thread::yield_now();
+    }
+    sema.remove_permits(2);
+    assert_eq!(Some(AcquireError::NoCapacity), waiter.join().unwrap());
+}
+
+#[test]
+fn try_access_notify_sends_after_release() {
+    use std::sync::mpsc;
+
+    let sema = Semaphore::new(1, ());
+    let (tx, rx) = mpsc::channel();
+    let guard = sema.try_access_notify(tx).expect("guard acquisition failed");
+    let coordinator = {
+        let sema = sema.clone();
+        thread::spawn(move || {
+            rx.recv().expect("release was not notified");
+            // The access has already been released by the time the message arrives.
+            sema.try_access().is_ok()
+        })
+    };
+    drop(guard);
+    assert!(coordinator.join().unwrap());
+
+    // A receiver that is gone does not affect the release.
+    let (tx, rx) = mpsc::channel();
+    drop(rx);
+    drop(sema.try_access_notify(tx).expect("guard acquisition failed"));
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn donate_moves_capacity_until_reclaimed() {
+    let idle = Semaphore::new(3, ());
+    let busy = Semaphore::new(1, ());
+    let held = busy.try_access().expect("guard acquisition failed");
+    let donation = idle.donate(&busy, 2);
+    assert_eq!(2, donation.amount());
+    assert_eq!(1, idle.capacity());
+    assert_eq!(3, busy.capacity());
+    assert_eq!(1, busy.active_count());
+    let extra = busy.try_access_n(2).expect("guard acquisition failed");
+    donation.reclaim();
+    assert_eq!(3, idle.capacity());
+    assert_eq!(1, busy.capacity());
+    drop(extra);
+    drop(held);
+
+    // No more than the donating semaphore has can be donated.
+    let donation = idle.donate(&busy, 5);
+    assert_eq!(3, donation.amount());
+    assert_eq!(0, idle.capacity());
+    assert_eq!(4, busy.capacity());
+    drop(donation);
+    assert_eq!(3, idle.capacity());
+    assert_eq!(1, busy.capacity());
+}
+
+#[test]
+fn paused_guard_lets_others_in_until_resumed() {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access_tagged("worker").expect("guard acquisition failed");
+    let paused = guard.pause();
+    assert_eq!(0, sema.active_count());
+    let other = sema.try_access().expect("capacity not freed by pause");
+    let resumer = thread::spawn(move || paused.resume_blocking());
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(1, sema.active_count());
+    drop(other);
+    let resumed = resumer.join().unwrap().expect("guard acquisition failed");
+    assert_eq!(Some("worker"), resumed.tag());
+    assert_eq!(1, sema.active_count());
+
+    let paused = resumed.pause();
+    let other = sema.try_access().expect("capacity not freed by pause");
+    assert_eq!(paused.resume().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(other);
+    let paused = sema.try_access().expect("guard acquisition failed").pause();
+    paused.resume().expect("guard acquisition failed");
+}
+
+#[test]
+fn tiered_falls_back_to_burst_once_base_is_exhausted() {
+    use super::{Tier, TieredSemaphore};
+
+    let sema = TieredSemaphore::new(1, 1, ());
+    let first = sema.try_access().expect("guard acquisition failed");
+    let second = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(Tier::Base, first.tier());
+    assert_eq!(Tier::Burst, second.tier());
+    assert_eq!(sema.try_access().err().unwrap(),
+        TryAccessError::NoCapacity);
+    drop(first);
+    assert_eq!(Tier::Base, sema.try_access().expect("guard acquisition failed").tier());
+    drop(second);
+    assert_eq!(1, sema.bucket(Tier::Base).capacity());
+    assert_eq!(1, sema.bucket(Tier::Burst).capacity());
+}
+
+#[test]
+fn tiered_policy_migrates_released_capacity() {
+    use super::{ReplenishPolicy, Tier, TieredSemaphore};
+
+    let sema = TieredSemaphore::new(1, 2, ());
+    let base = sema.try_access().expect("guard acquisition failed");
+    let burst = sema.try_access().expect("guard acquisition failed");
+    assert_eq!(Tier::Burst, burst.tier());
+    sema.set_policy(ReplenishPolicy::Base);
+    // Access from the burst bucket drains its capacity into the base bucket.
+    drop(burst);
+    assert_eq!(2, sema.bucket(Tier::Base).capacity());
+    assert_eq!(1, sema.bucket(Tier::Burst).capacity());
+    assert_eq!(0, sema.bucket(Tier::Burst).active_count());
+    // Access from the base bucket returns where it came from.
+    drop(base);
+    assert_eq!(2, sema.bucket(Tier::Base).capacity());
+    assert_eq!(0, sema.bucket(Tier::Base).active_count());
+
+    sema.set_policy(ReplenishPolicy::Burst);
+    drop(sema.try_access().expect("guard acquisition failed"));
+    assert_eq!(1, sema.bucket(Tier::Base).capacity());
+    assert_eq!(2, sema.bucket(Tier::Burst).capacity());
+}
+
+#[test]
+fn release_some_refuses_to_release_part_of_a_lease() {
+    let sema = Semaphore::new(2, ());
+    let mut guard = sema.lease(Duration::from_secs(5)).expect("guard acquisition failed");
+    assert_eq!(0, guard.release_some(1));
+    assert_eq!(1, guard.permits());
+    assert_eq!(1, sema.active_count());
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn over_release_reports_caller_location() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let sema = Semaphore::new(2, ());
+    let mut guard = sema.try_access().expect("guard acquisition failed");
+    let line = line!() + 1;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| guard.release_some(2)));
+    let payload = result.expect_err("over-release did not panic");
+    let message = payload.downcast_ref::<String>().expect("panic message missing");
+    assert!(message.contains("released more accesses than held by the guard"));
+    assert!(message.contains(&format!("{}:{}", file!(), line)), "{}", message);
+    drop(guard);
+    assert_eq!(0, sema.active_count());
+}
+
+#[test]
+fn guard_reads_and_writes_through_exclusive_resource() {
+    use std::io::{self, Cursor, Write};
+
+    let sema = Semaphore::new(1, Cursor::new(b"hello".to_vec()));
+    let mut guard = sema.try_access().expect("guard acquisition failed");
+    let mut copied = Vec::new();
+    io::copy(&mut guard, &mut copied).expect("copy failed");
+    assert_eq!(b"hello".to_vec(), copied);
+    guard.write_all(b" world").expect("write failed");
+    guard.flush().expect("flush failed");
+    drop(guard);
+    assert_eq!(b"hello world".to_vec(), sema.shutdown().wait().unwrap().into_inner());
+
+    // With room for other guards, the resource can not be borrowed mutably.
+    let sema = Semaphore::new(2, Cursor::new(b"hello".to_vec()));
+    let mut guard = sema.try_access().expect("guard acquisition failed");
+    let err = io::copy(&mut guard, &mut io::sink()).expect_err("read from shared resource");
+    assert_eq!(io::ErrorKind::Other, err.kind());
+}
+
+fn queue_fair_waiter_then_release<F: FnOnce(&Semaphore<()>)>(shrink: F) -> AcquireError {
+    let sema = Semaphore::new(1, ());
+    let guard = sema.try_access().expect("guard acquisition failed");
+    let waiter = {
+        let sema = sema.clone();
+        thread::spawn(move || sema.access_fair_timeout(Duration::from_millis(200)).map(|_| ()))
+    };
+    while sema.inner.raw.waiter_count() == 0 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    shrink(&sema);
+    drop(guard);
+    let err = waiter.join().unwrap().expect_err("fair waiter granted access beyond capacity");
+    assert_eq!(0, sema.active_count());
+    err
+}
+
+#[test]
+fn release_is_not_handed_to_fair_waiter_after_close_gate() {
+    assert_eq!(AcquireError::Timeout, queue_fair_waiter_then_release(|sema| sema.close_gate()));
+}
+
+#[test]
+fn release_is_not_handed_to_fair_waiter_after_remove_permits() {
+    assert_eq!(AcquireError::Timeout, queue_fair_waiter_then_release(|sema| sema.remove_permits(1)));
+}
+
+#[test]
+fn into_inner_fails_while_weak_handle_exists() {
+    let sema = Semaphore::new(1, 42);
+    let weak = sema.downgrade();
+    let sema = sema.into_inner().expect_err("succeeded despite weak handle");
+    assert!(weak.upgrade().is_some_and(|upgraded| upgraded.try_access().is_ok()));
+    drop(weak);
+    assert_eq!(Ok(42), sema.into_inner().map_err(|_| ()));
+}
@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Number of seconds `Semaphore::recent_throughput` averages over.
+pub const THROUGHPUT_WINDOW_SECS: usize = 10;
+
+/// Second a bucket has never been used for.
+const UNUSED: u64 = u64::MAX;
+
+/// Counts of the events within one second since the creation of the `Throughput`.
+struct Bucket {
+    second: AtomicU64,
+    acquires: AtomicU64,
+    releases: AtomicU64
+}
+
+/// Sliding window of acquire and release counts, bucketed by second.
+///
+/// Buckets are reused in a ring once their second has left the window. Recording is
+/// lock-free, at the cost of possibly losing an event that races with the reuse of its bucket.
+pub struct Throughput {
+    start: Instant,
+    buckets: [Bucket; THROUGHPUT_WINDOW_SECS]
+}
+
+impl Throughput {
+    pub fn new(start: Instant) -> Throughput {
+        Throughput {
+            start,
+            buckets: [(); THROUGHPUT_WINDOW_SECS].map(|_| Bucket {
+                second: AtomicU64::new(UNUSED),
+                acquires: AtomicU64::default(),
+                releases: AtomicU64::default()
+            })
+        }
+    }
+
+    fn bucket(&self, now: Instant) -> &Bucket {
+        let second = now.saturating_duration_since(self.start).as_secs();
+        let bucket = &self.buckets[second as usize % THROUGHPUT_WINDOW_SECS];
+        let stamp = bucket.second.load(Ordering::Acquire);
+        if stamp != second
+            && bucket.second.compare_exchange(stamp, second, Ordering::AcqRel, Ordering::Acquire).is_ok()
+        {
+            bucket.acquires.store(0, Ordering::Relaxed);
+            bucket.releases.store(0, Ordering::Relaxed);
+        }
+        bucket
+    }
+
+    pub fn record_acquire(&self, now: Instant) {
+        self.bucket(now).acquires.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_release(&self, now: Instant) {
+        self.bucket(now).releases.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Acquires and releases per second over the window ending at `now`.
+    ///
+    /// Until the semaphore has existed for the whole window, the rates are averaged
+    /// over its lifetime instead, though at least over one second.
+    pub fn rates(&self, now: Instant) -> (f64, f64) {
+        let elapsed = now.saturating_duration_since(self.start);
+        let second = elapsed.as_secs();
+        let (mut acquires, mut releases) = (0, 0);
+        for bucket in &self.buckets {
+            let stamp = bucket.second.load(Ordering::Acquire);
+            if stamp != UNUSED && stamp <= second && second - stamp < THROUGHPUT_WINDOW_SECS as u64 {
+                acquires += bucket.acquires.load(Ordering::Relaxed);
+                releases += bucket.releases.load(Ordering::Relaxed);
+            }
+        }
+        let span = elapsed.as_secs_f64().clamp(1.0, THROUGHPUT_WINDOW_SECS as f64);
+        (acquires as f64 / span, releases as f64 / span)
+    }
+}
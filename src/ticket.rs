@@ -0,0 +1,66 @@
+use guard;
+use super::{Semaphore, TryAccessError, TryAccessResult};
+
+/// Place in the queue of fair acquirers of a semaphore.
+///
+/// Returned from `Semaphore::enqueue`. The ticket holds its place until it is waited for
+/// through `wait`, which grants access like `Semaphore::access_fair`. Dropping the ticket
+/// gives up its place, passing on any access that was already handed to it.
+pub struct WaitTicket<'a, T: ?Sized + 'a> {
+    semaphore: &'a Semaphore<T>,
+    ticket: Option<usize>
+}
+
+pub fn new<T: ?Sized>(semaphore: &Semaphore<T>) -> WaitTicket<'_, T> {
+    WaitTicket {
+        semaphore,
+        ticket: Some(semaphore.inner.raw.enqueue_fair())
+    }
+}
+
+impl<'a, T: ?Sized> WaitTicket<'a, T> {
+    #[inline]
+    /// Number of fair acquirers ahead of this one in the queue.
+    ///
+    /// Starts out at the number of fair acquirers that were queued when the ticket was taken,
+    /// and decreases as they are served or give up. A position of `0` means this acquirer
+    /// is next in line.
+    pub fn position(&self) -> usize {
+        self.semaphore.inner.raw.fair_position(self.ticket.unwrap())
+    }
+
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Block the current thread until it is the turn of this ticket, and access the resource.
+    ///
+    /// Fails right away with `TryAccessError::NoCapacity` if the semaphore has a capacity
+    /// of zero, and returns `TryAccessError::Shutdown` or `TryAccessError::Poisoned`
+    /// if the semaphore is shut down or poisoned.
+    pub fn wait(mut self) -> TryAccessResult<T> {
+        let raw = &self.semaphore.inner.raw;
+        if raw.is_poisoned() {
+            return Err(TryAccessError::Poisoned);
+        }
+        if raw.capacity() == 0 && !raw.is_gated() {
+            return Err(TryAccessError::NoCapacity);
+        }
+        let ticket = self.ticket.take().unwrap();
+        if !raw.wait_fair(ticket, None) {
+            return Err(TryAccessError::Shutdown);
+        }
+        if !raw.is_closed() {
+            if let Some(ref resource) = *self.semaphore.inner.resource.read() {
+                return Ok(guard::new(raw, resource));
+            }
+        }
+        raw.release();
+        Err(TryAccessError::Shutdown)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for WaitTicket<'a, T> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            self.semaphore.inner.raw.leave_fair(ticket);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use guard;
+use super::{Semaphore, SemaphoreGuard, TryAccessError};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Bucket of a `TieredSemaphore` that access was acquired from.
+pub enum Tier {
+    /// The bucket of capacity for normal conditions, which is tried first.
+    Base,
+    /// The bucket of overflow capacity, which is only used once the base bucket is exhausted.
+    Burst
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Policy of a `TieredSemaphore` deciding which bucket released access replenishes.
+pub enum ReplenishPolicy {
+    /// Access is returned to the bucket it was acquired from, keeping both capacities unchanged.
+    Origin,
+    /// Access is returned to the base bucket, migrating capacity out of the burst bucket.
+    Base,
+    /// Access is returned to the burst bucket, migrating capacity out of the base bucket.
+    Burst
+}
+
+impl ReplenishPolicy {
+    fn destination(self, origin: Tier) -> Tier {
+        match self {
+            ReplenishPolicy::Origin => origin,
+            ReplenishPolicy::Base => Tier::Base,
+            ReplenishPolicy::Burst => Tier::Burst
+        }
+    }
+}
+
+/// Semaphore with a base and a burst bucket of capacity over the same resource.
+///
+/// Acquisitions are granted from the base bucket while it has capacity, and fall back
+/// to the burst bucket once it is exhausted. When access is released, the replenish policy
+/// decides which bucket it returns to. Returning access to the other bucket than it was
+/// acquired from moves one unit of capacity between the buckets, so the capacity of the
+/// burst bucket can be drained into the base bucket once bursts are over, or the other way.
+/// The total capacity of both buckets never changes.
+pub struct TieredSemaphore<T: ?Sized> {
+    base: Semaphore<T>,
+    burst: Semaphore<T>,
+    policy: Arc<Mutex<ReplenishPolicy>>
+}
+
+impl<T: ?Sized> Clone for TieredSemaphore<T> {
+    fn clone(&self) -> TieredSemaphore<T> {
+        TieredSemaphore {
+            base: self.base.clone(),
+            burst: self.burst.clone(),
+            policy: self.policy.clone()
+        }
+    }
+}
+
+impl<T> TieredSemaphore<T> {
+    /// Create a new tiered semaphore around a resource, with the given capacities of its buckets.
+    ///
+    /// Released access returns to the bucket it was acquired from, until the policy is changed.
+    pub fn new(base: usize, burst: usize, resource: T) -> Self {
+        let resource = Arc::new(resource);
+        TieredSemaphore {
+            base: Semaphore::from_arc(base, resource.clone()),
+            burst: Semaphore::from_arc(burst, resource),
+            policy: Arc::new(Mutex::new(ReplenishPolicy::Origin))
+        }
+    }
+}
+
+impl<T: ?Sized> TieredSemaphore<T> {
+    #[cfg_attr(feature = "debug-holders", track_caller)]
+    /// Attempt to access the underlying resource, from the base bucket if it has capacity
+    /// and from the burst bucket otherwise.
+    ///
+    /// Fails with `TryAccessError::NoCapacity` if both buckets are exhausted, and
+    /// otherwise in the same way as `Semaphore::try_access` on the base bucket.
+    pub fn try_access(&self) -> Result<TieredGuard<T>, TryAccessError> {
+        let (guard, tier) = match self.base.try_access() {
+            Ok(guard) => (guard, Tier::Base),
+            Err(TryAccessError::NoCapacity) => (self.burst.try_access()?, Tier::Burst),
+            Err(err) => return Err(err)
+        };
+        Ok(TieredGuard {
+            guard: Some(guard),
+            tier,
+            semaphore: self.clone()
+        })
+    }
+
+    #[inline]
+    /// Policy deciding which bucket released access replenishes.
+    pub fn policy(&self) -> ReplenishPolicy {
+        *self.policy.lock()
+    }
+
+    /// Change which bucket released access replenishes, including that of guards already held.
+    pub fn set_policy(&self, policy: ReplenishPolicy) {
+        *self.policy.lock() = policy;
+    }
+
+    #[inline]
+    /// Borrow the semaphore of the given bucket, for example to read its capacity.
+    pub fn bucket(&self, tier: Tier) -> &Semaphore<T> {
+        match tier {
+            Tier::Base => &self.base,
+            Tier::Burst => &self.burst
+        }
+    }
+}
+
+/// RAII guard of a `TieredSemaphore`, which replenishes a bucket according to its policy on drop.
+///
+/// Returned from `TieredSemaphore::try_access`.
+pub struct TieredGuard<T: ?Sized> {
+    guard: Option<SemaphoreGuard<T>>,
+    tier: Tier,
+    semaphore: TieredSemaphore<T>
+}
+
+impl<T: ?Sized> TieredGuard<T> {
+    #[inline]
+    /// Bucket the access was acquired from.
+    pub fn tier(&self) -> Tier {
+        self.tier
+    }
+}
+
+impl<T: ?Sized> Drop for TieredGuard<T> {
+    fn drop(&mut self) {
+        let mut guard = self.guard.take().unwrap();
+        let destination = self.semaphore.policy().destination(self.tier);
+        if destination == self.tier {
+            return;
+        }
+        // The origin gives up the capacity of the access before it is released, so that
+        // none of its waiters are woken for it, and the destination takes the capacity over.
+        guard::set_borrowed(&mut guard);
+        drop(guard);
+        self.semaphore.bucket(destination).add_permits(1);
+    }
+}
+
+impl<T: ?Sized> Deref for TieredGuard<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
@@ -0,0 +1,26 @@
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+
+/// Number of grants a `FairnessTrace` retains, after which the oldest are discarded.
+pub const FAIRNESS_TRACE_LEN: usize = 1024;
+
+/// Bounded log of the acquirers that were granted access, in the order of the grants.
+#[derive(Default)]
+pub struct FairnessTrace {
+    grants: Mutex<VecDeque<usize>>
+}
+
+impl FairnessTrace {
+    pub fn record(&self, acquirer: usize) {
+        let mut grants = self.grants.lock();
+        if grants.len() == FAIRNESS_TRACE_LEN {
+            grants.pop_front();
+        }
+        grants.push_back(acquirer);
+    }
+
+    pub fn snapshot(&self) -> Vec<usize> {
+        self.grants.lock().iter().cloned().collect()
+    }
+}
@@ -0,0 +1,45 @@
+use std::ops::Deref;
+
+use super::SemaphoreGuard;
+
+/// RAII guard holding exactly `N` accesses, with the number encoded in its type.
+///
+/// Returned from `Semaphore::try_access_typed`. Unlike a `SemaphoreGuard` holding many
+/// accesses, the number is part of the signature of any function taking the guard, so
+/// guards of different weights can not be mixed up. All `N` accesses are released together
+/// when the guard is dropped.
+///
+/// ```
+/// # use semaphore::{Semaphore, TypedGuard};
+/// fn heavy_work(_guard: &TypedGuard<(), 2>) {}
+///
+/// let sema = Semaphore::new(3, ());
+/// let guard = sema.try_access_typed::<2>().unwrap();
+/// heavy_work(&guard);
+/// assert_eq!(2, sema.active_count());
+/// ```
+pub struct TypedGuard<T: ?Sized, const N: usize> {
+    guard: SemaphoreGuard<T>
+}
+
+pub fn new<T: ?Sized, const N: usize>(guard: SemaphoreGuard<T>) -> TypedGuard<T, N> {
+    debug_assert_eq!(N, guard.permits());
+    TypedGuard { guard }
+}
+
+impl<T: ?Sized, const N: usize> TypedGuard<T, N> {
+    #[inline]
+    /// Turn this into a guard whose number of accesses is only known at runtime.
+    pub fn into_guard(self) -> SemaphoreGuard<T> {
+        self.guard
+    }
+}
+
+impl<T: ?Sized, const N: usize> Deref for TypedGuard<T, N> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::{Arc, Weak};
+
+use super::{Inner, Semaphore};
+
+/// Handle to a semaphore that does not keep it alive.
+///
+/// Returned from `Semaphore::downgrade`. It does not count towards `Semaphore::handle_count`,
+/// but since it could be upgraded to a new clone at any time, `Semaphore::into_inner` and
+/// `Semaphore::try_into_owned` fail while it exists.
+pub struct WeakSemaphore<T: ?Sized> {
+    inner: Weak<Inner<T>>
+}
+
+pub fn new<T: ?Sized>(semaphore: &Semaphore<T>) -> WeakSemaphore<T> {
+    WeakSemaphore {
+        inner: Arc::downgrade(&semaphore.inner)
+    }
+}
+
+impl<T: ?Sized> Clone for WeakSemaphore<T> {
+    fn clone(&self) -> WeakSemaphore<T> {
+        WeakSemaphore {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+impl<T: ?Sized> WeakSemaphore<T> {
+    /// Get the semaphore back, if any clone of it is still alive.
+    pub fn upgrade(&self) -> Option<Semaphore<T>> {
+        self.inner.upgrade().map(|inner| Semaphore { inner })
+    }
+}